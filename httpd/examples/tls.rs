@@ -0,0 +1,39 @@
+// Serves HTTPS on 8443 with a self-signed certificate for localhost, and
+// redirects plain HTTP on 8080 to it. Run with:
+//
+//     cargo run --example tls --features tls
+//     curl --cacert examples/certs/localhost.pem https://localhost:8443/
+
+use httpd::{
+    http::{Response, Router, StatusCode},
+    server::Server,
+    tls::TlsConfig,
+};
+use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
+
+// made with:
+// openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -days 3650 \
+//     -subj /CN=localhost -addext "subjectAltName=DNS:localhost,IP:127.0.0.1" \
+//     -keyout localhost-key.pem -out localhost.pem
+const CERT: &[u8] = include_bytes!("certs/localhost.pem");
+const KEY: &[u8] = include_bytes!("certs/localhost-key.pem");
+
+fn main() {
+    TermLogger::init(LevelFilter::Info, Config::default(), TerminalMode::Mixed, ColorChoice::Auto).unwrap();
+
+    let tls = TlsConfig::from_pem(CERT, KEY).expect("the bundled certificate loads");
+
+    let mut router = Router::new();
+    router.get("/", |_| {
+        Response::builder(StatusCode::Ok)
+            .content_type("text/html; charset=utf-8")
+            .body("<h1>Hello over TLS!</h1>")
+            .build()
+    });
+
+    Server::new(String::from("127.0.0.1:8443"), router)
+        .tls(tls)
+        .redirect_http(String::from("127.0.0.1:8080"))
+        .handle_signals(true)
+        .run()
+}
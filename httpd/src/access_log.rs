@@ -0,0 +1,167 @@
+use std::{
+    fmt::Write as _,
+    io::Write,
+    net::SocketAddr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use crate::http::date;
+
+/// The `log` target access log lines are written to, unless the access log
+/// has its own writer.
+pub const LOG_TARGET: &str = "httpd::access";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] "GET / HTTP/1.1" 200 12`
+    Common,
+    /// Common followed by the quoted `Referer` and `User-Agent`.
+    Combined,
+    /// One JSON object per line, including how long the request took.
+    Json,
+}
+
+/// Where and how every served request is recorded.
+///
+/// By default lines go to the `log` crate at info level with the
+/// `httpd::access` target, so they can be filtered apart from the server's
+/// own messages:
+///
+/// ```ignore
+/// let log = File::create("access.log")?;
+/// Server::new(addr, router).access_log(AccessLog::new(LogFormat::Combined).writer(log))
+/// ```
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Sink,
+}
+
+enum Sink {
+    Log,
+    Writer(Mutex<Box<dyn Write + Send>>),
+    Off,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat) -> Self {
+        Self { format, sink: Sink::Log }
+    }
+
+    /// An access log that records nothing.
+    pub fn disabled() -> Self {
+        Self { format: LogFormat::Common, sink: Sink::Off }
+    }
+
+    /// Writes lines to `writer` instead of the `log` crate.
+    pub fn writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.sink = Sink::Writer(Mutex::new(Box::new(writer)));
+        self
+    }
+
+    pub(crate) fn record(&self, record: &AccessRecord) {
+        match &self.sink {
+            Sink::Off => {}
+            Sink::Log => log::info!(target: LOG_TARGET, "{}", self.format(record)),
+            Sink::Writer(writer) => {
+                let mut writer = writer.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Err(e) = writeln!(writer, "{}", self.format(record)) {
+                    log::error!("Failed to write access log: {}", e);
+                }
+            }
+        }
+    }
+
+    fn format(&self, r: &AccessRecord) -> String {
+        let addr = r.remote_addr.map_or("-".to_string(), |a| a.ip().to_string());
+        let mut line = String::new();
+        match self.format {
+            LogFormat::Common | LogFormat::Combined => {
+                let _ = write!(
+                    line,
+                    "{} - - [{}] \"{}\" {} {}",
+                    addr,
+                    date::fmt_clf_date(r.time),
+                    escape_clf(&r.request_line),
+                    r.status,
+                    // CLF writes an empty body as "-"
+                    r.bytes.filter(|b| *b > 0).map_or("-".to_string(), |b| b.to_string()),
+                );
+                if self.format == LogFormat::Combined {
+                    let _ = write!(
+                        line,
+                        " \"{}\" \"{}\"",
+                        escape_clf(r.referer.as_deref().unwrap_or("-")),
+                        escape_clf(r.user_agent.as_deref().unwrap_or("-")),
+                    );
+                }
+            }
+            LogFormat::Json => {
+                let _ = write!(
+                    line,
+                    "{{\"time\":{},\"remote_addr\":{},\"request\":{},\"status\":{},\"bytes\":{},\"duration_ms\":{:.3},\"referer\":{},\"user_agent\":{}}}",
+                    json_string(&date::fmt_rfc3339(r.time)),
+                    json_string(&addr),
+                    json_string(&r.request_line),
+                    r.status,
+                    r.bytes.map_or("null".to_string(), |b| b.to_string()),
+                    r.duration.as_secs_f64() * 1000.0,
+                    r.referer.as_deref().map_or("null".to_string(), json_string),
+                    r.user_agent.as_deref().map_or("null".to_string(), json_string),
+                );
+            }
+        }
+        line
+    }
+}
+
+/// What the access log knows about a request.
+#[derive(Debug)]
+pub(crate) struct AccessRecord {
+    pub(crate) remote_addr: Option<SocketAddr>,
+    /// When the request head was received.
+    pub(crate) time: SystemTime,
+    pub(crate) request_line: String,
+    pub(crate) referer: Option<String>,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) status: u16,
+    /// Body bytes sent, None if sending failed.
+    pub(crate) bytes: Option<u64>,
+    pub(crate) duration: Duration,
+}
+
+// quotes and control characters would let a client forge log fields
+fn escape_clf(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\x{:02x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -0,0 +1,377 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    io::{self, BufRead, ErrorKind, Read},
+};
+
+use super::{Headers, StatusCode};
+
+// chunk-size lines and trailers are tiny, anything longer is garbage
+const MAX_LINE_LEN: u64 = 4096;
+
+#[derive(Debug, Clone, Copy)]
+enum Framing {
+    Length(u64),
+    Chunked(ChunkState),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ChunkState {
+    Size,
+    Data(u64),
+    DataEnd,
+    Done,
+}
+
+/// Reads a request body off a connection, undoing its transfer framing.
+///
+/// The reader never consumes past the end of the body, so whatever follows
+/// it in `inner` is left for the next request.
+#[derive(Debug)]
+pub struct BodyReader<R> {
+    inner: R,
+    framing: Framing,
+    limit: u64,
+    total: u64,
+    exceeded: bool,
+}
+
+impl<R: BufRead> BodyReader<R> {
+    pub fn new(inner: R, headers: &Headers, limit: u64) -> Result<Self, BodyError> {
+        let framing = match (headers.get_all("Transfer-Encoding"), headers.get_all("Content-Length")) {
+            // both present is a request smuggling vector (RFC 7230 3.3.3)
+            (Some(_), Some(_)) => return Err(BodyError::InvalidFraming),
+            (Some(codings), None) => {
+                let mut codings = codings.iter().flat_map(|v| v.split(',')).map(str::trim);
+                match (codings.next(), codings.next()) {
+                    (Some(c), None) if c.eq_ignore_ascii_case("chunked") => Framing::Chunked(ChunkState::Size),
+                    _ => return Err(BodyError::UnsupportedEncoding),
+                }
+            }
+            (None, Some(lengths)) => {
+                let len = parse_number(lengths[0], 10).ok_or(BodyError::InvalidFraming)?;
+                if lengths.iter().any(|l| *l != lengths[0]) {
+                    return Err(BodyError::InvalidFraming);
+                }
+                if len > limit {
+                    return Err(BodyError::TooLarge);
+                }
+                Framing::Length(len)
+            }
+            (None, None) => Framing::Length(0),
+        };
+
+        Ok(Self { inner, framing, limit, total: 0, exceeded: false })
+    }
+
+    /// Whether the body is known to be empty without reading anything.
+    pub fn is_empty(&self) -> bool {
+        matches!(self.framing, Framing::Length(0))
+    }
+
+    /// Reads the whole body into memory, failing once it grows past the limit.
+    pub fn read_all(mut self) -> Result<Vec<u8>, BodyError> {
+        let mut body = Vec::new();
+        match self.read_to_end(&mut body) {
+            Ok(_) => Ok(body),
+            Err(_) if self.exceeded => Err(BodyError::TooLarge),
+            Err(e) if e.kind() == ErrorKind::InvalidData => Err(BodyError::InvalidFraming),
+            Err(e) => Err(BodyError::Io(e)),
+        }
+    }
+
+    /// Decodes the part of the body that `input` holds into `body`, for
+    /// callers that can't block waiting for the rest. Returns how much of
+    /// `input` was used and whether the body is complete. A chunk line cut
+    /// off at the end of `input` is left for the next call.
+    #[cfg(feature = "async")]
+    pub(crate) fn feed(&mut self, input: &[u8], body: &mut Vec<u8>) -> Result<(usize, bool), BodyError> {
+        let mut reader = BodyReader {
+            inner: input,
+            framing: self.framing,
+            limit: self.limit,
+            total: self.total,
+            exceeded: false,
+        };
+        let mut buf = [0; 8 * 1024];
+        let done = loop {
+            // a read that runs out of input is undone and retried with more
+            let saved = (reader.inner, reader.framing, reader.total);
+            match reader.read(&mut buf) {
+                Ok(0) => break true,
+                Ok(n) => body.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    (reader.inner, reader.framing, reader.total) = saved;
+                    break false;
+                }
+                Err(_) if reader.exceeded => return Err(BodyError::TooLarge),
+                Err(e) if e.kind() == ErrorKind::InvalidData => return Err(BodyError::InvalidFraming),
+                Err(e) => return Err(BodyError::Io(e)),
+            }
+        };
+        self.framing = reader.framing;
+        self.total = reader.total;
+        Ok((input.len() - reader.inner.len(), done))
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_chunked(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Framing::Chunked(state) = &mut self.framing else {
+                unreachable!()
+            };
+            match *state {
+                ChunkState::Size => {
+                    let line = read_line(&mut self.inner)?;
+                    // chunk extensions are ignored
+                    let size = line.split(';').next().unwrap_or("").trim();
+                    let size = parse_number(size, 16)
+                        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "invalid chunk size"))?;
+                    if size == 0 {
+                        // trailers are read and dropped
+                        while !read_line(&mut self.inner)?.is_empty() {}
+                        *state = ChunkState::Done;
+                    } else {
+                        *state = ChunkState::Data(size);
+                    }
+                }
+                ChunkState::Data(remaining) => {
+                    let max = remaining.min(buf.len() as u64) as usize;
+                    let n = self.inner.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(ErrorKind::UnexpectedEof.into());
+                    }
+                    *state = match remaining - n as u64 {
+                        0 => ChunkState::DataEnd,
+                        left => ChunkState::Data(left),
+                    };
+                    return Ok(n);
+                }
+                ChunkState::DataEnd => {
+                    if !read_line(&mut self.inner)?.is_empty() {
+                        return Err(io::Error::new(ErrorKind::InvalidData, "missing CRLF after chunk"));
+                    }
+                    *state = ChunkState::Size;
+                }
+                ChunkState::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match &mut self.framing {
+            Framing::Length(0) => return Ok(0),
+            Framing::Length(remaining) => {
+                let max = (*remaining).min(buf.len() as u64) as usize;
+                let n = self.inner.read(&mut buf[..max])?;
+                if n == 0 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                *remaining -= n as u64;
+                n
+            }
+            Framing::Chunked(_) => self.read_chunked(buf)?,
+        };
+
+        self.total += n as u64;
+        if self.total > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::new(ErrorKind::InvalidData, "body exceeds limit"));
+        }
+        Ok(n)
+    }
+}
+
+// digits only, as `from_str_radix` alone would also take a leading "+"
+fn parse_number(s: &str, radix: u32) -> Option<u64> {
+    if s.is_empty() || !s.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u64::from_str_radix(s, radix).ok()
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = Vec::new();
+    let n = reader.take(MAX_LINE_LEN).read_until(b'\n', &mut line)?;
+    if line.last() != Some(&b'\n') {
+        // either the line is too long or the input ended partway through it
+        return Err(match n as u64 {
+            MAX_LINE_LEN => io::Error::new(ErrorKind::InvalidData, "line too long"),
+            _ => ErrorKind::UnexpectedEof.into(),
+        });
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub enum BodyError {
+    TooLarge,
+    InvalidFraming,
+    UnsupportedEncoding,
+    Io(io::Error),
+}
+
+impl BodyError {
+    fn error(&self) -> &str {
+        match self {
+            Self::TooLarge => "Body Too Large",
+            Self::InvalidFraming => "Invalid Body Framing",
+            Self::UnsupportedEncoding => "Unsupported Transfer Encoding",
+            Self::Io(_) => "Body Read Failed",
+        }
+    }
+
+    /// Status code to answer with when the body could not be read.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::TooLarge => StatusCode::PayloadTooLarge,
+            Self::UnsupportedEncoding => StatusCode::NotImplemented,
+            Self::InvalidFraming | Self::Io(_) => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl From<io::Error> for BodyError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Error for BodyError {}
+
+impl Display for BodyError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Io(e) => write!(f, "{}: {}", self.error(), e),
+            _ => write!(f, "{}", self.error()),
+        }
+    }
+}
+
+impl Debug for BodyError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader<'a>(
+        headers: &[(&'a str, &'a str)],
+        input: &'a [u8],
+        limit: u64,
+    ) -> Result<BodyReader<&'a [u8]>, BodyError> {
+        let mut map = Headers::new();
+        for (name, value) in headers {
+            map.insert(name, value);
+        }
+        BodyReader::new(input, &map, limit)
+    }
+
+    fn chunked(input: &[u8]) -> BodyReader<&[u8]> {
+        reader(&[("Transfer-Encoding", "chunked")], input, 1024).unwrap()
+    }
+
+    #[test]
+    fn reads_a_content_length_body_and_leaves_the_rest() {
+        let mut body = reader(&[("Content-Length", "5")], b"helloGET /", 1024).unwrap();
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello");
+        assert_eq!(body.into_inner(), b"GET /");
+    }
+
+    #[test]
+    fn no_framing_headers_means_no_body() {
+        let body = reader(&[], b"GET /", 1024).unwrap();
+        assert!(body.is_empty());
+        assert_eq!(body.read_all().unwrap(), b"");
+    }
+
+    #[test]
+    fn rejects_conflicting_or_invalid_framing() {
+        let both = [("Content-Length", "5"), ("Transfer-Encoding", "chunked")];
+        assert!(matches!(reader(&both, b"", 1024), Err(BodyError::InvalidFraming)));
+        let differing = [("Content-Length", "5"), ("Content-Length", "6")];
+        assert!(matches!(reader(&differing, b"", 1024), Err(BodyError::InvalidFraming)));
+        for len in ["abc", "+5", "-5", " 5", "0x5", "", "18446744073709551616"] {
+            let body = reader(&[("Content-Length", len)], b"", 1024);
+            assert!(matches!(body, Err(BodyError::InvalidFraming)), "{:?}", len);
+        }
+        assert!(matches!(reader(&[("Transfer-Encoding", "gzip")], b"", 1024), Err(BodyError::UnsupportedEncoding)));
+    }
+
+    #[test]
+    fn rejects_a_declared_length_over_the_limit() {
+        assert!(matches!(reader(&[("Content-Length", "2000")], b"", 1024), Err(BodyError::TooLarge)));
+    }
+
+    #[test]
+    fn a_short_body_is_an_io_error() {
+        let body = reader(&[("Content-Length", "10")], b"hello", 1024).unwrap();
+        assert!(matches!(body.read_all(), Err(BodyError::Io(_))));
+    }
+
+    #[test]
+    fn decodes_chunks_and_skips_extensions_and_trailers() {
+        let mut body = chunked(b"5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nExpires: never\r\n\r\nGET /");
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, world");
+        assert_eq!(body.into_inner(), b"GET /");
+    }
+
+    #[test]
+    fn rejects_malformed_chunks() {
+        for input in [
+            &b"z\r\nhello\r\n0\r\n\r\n"[..],
+            b"+5\r\nhello\r\n0\r\n\r\n",
+            b"0x5\r\nhello\r\n0\r\n\r\n",
+            b"\r\nhello\r\n0\r\n\r\n",
+            b"10000000000000000\r\n",
+            b"5\r\nhelloXX0\r\n\r\n",
+        ] {
+            assert!(matches!(chunked(input).read_all(), Err(BodyError::InvalidFraming)), "{:?}", input);
+        }
+        let long_line = [&[b'1'; MAX_LINE_LEN as usize][..], b"\r\n"].concat();
+        assert!(matches!(chunked(&long_line).read_all(), Err(BodyError::InvalidFraming)));
+    }
+
+    #[test]
+    fn the_limit_counts_decoded_bytes() {
+        let input = b"1\r\na\r\n1\r\nb\r\n1\r\nc\r\n0\r\n\r\n";
+        let body = reader(&[("Transfer-Encoding", "chunked")], input, 2).unwrap();
+        assert!(matches!(body.read_all(), Err(BodyError::TooLarge)));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn feed_picks_up_where_the_input_was_cut_off() {
+        let input = b"5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n";
+        let mut body = chunked(b"");
+        let mut out = Vec::new();
+        let mut used = 0;
+        // hand the input over a byte at a time, as a slow client would
+        for end in 1..=input.len() {
+            let (n, done) = body.feed(&input[used..end], &mut out).unwrap();
+            used += n;
+            assert_eq!(done, end == input.len());
+        }
+        assert_eq!(used, input.len());
+        assert_eq!(out, b"hello, world");
+    }
+}
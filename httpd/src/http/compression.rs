@@ -0,0 +1,295 @@
+use std::io::{self, Read, Write};
+
+use brotli::CompressorWriter;
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression as Level,
+};
+
+use super::{
+    encoding::{self, add_vary, ContentEncoding},
+    Method, Middleware, Next, Request, Response, ResponseBody, StatusCode,
+};
+
+const DEFAULT_MIN_SIZE: u64 = 1024;
+const DEFAULT_CONTENT_TYPES: &[&str] = &[
+    "text/*",
+    "application/javascript",
+    "application/json",
+    "application/xml",
+    "application/wasm",
+    "image/svg+xml",
+];
+// quality 11 is meant for compressing ahead of time, not per request
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Middleware that compresses response bodies with the coding the client
+/// prefers in `Accept-Encoding`.
+///
+/// Only bodies of the listed content types are compressed, and only when
+/// they are at least `min_size` long. Responses that are already encoded,
+/// partial, or marked `Cache-Control: no-transform` are left alone.
+///
+/// Whenever a coding is chosen for a response its `ETag` is made weak, as
+/// the bytes differ from the identity body. 304 Not Modified and HEAD
+/// responses get the same headers the full response would.
+///
+/// ```ignore
+/// router.wrap(Compression::new().min_size(512));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: u64,
+    content_types: Vec<String>,
+    encodings: Vec<ContentEncoding>,
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self {
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES.iter().map(|t| t.to_string()).collect(),
+            encodings: vec![ContentEncoding::Brotli, ContentEncoding::Gzip, ContentEncoding::Deflate],
+        }
+    }
+
+    /// Smaller bodies go out as they are, 1 KiB unless changed. Streams of
+    /// unknown length are always compressed.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Replaces the content types worth compressing. `text/*` stands for
+    /// every text type, and `+json` and `+xml` types follow their base.
+    pub fn content_types(mut self, content_types: &[&str]) -> Self {
+        self.content_types = content_types.iter().map(|t| t.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// The codings to offer, preferred first. Brotli, gzip, then deflate
+    /// unless changed.
+    pub fn encodings(mut self, encodings: &[ContentEncoding]) -> Self {
+        self.encodings = encodings.to_vec();
+        self
+    }
+
+    fn compresses_type(&self, content_type: &str) -> bool {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        // application/ld+json compresses as well as application/json
+        let base = match mime.rsplit_once('+') {
+            Some((_, suffix @ ("json" | "xml"))) => format!("application/{}", suffix),
+            _ => mime.clone(),
+        };
+        self.content_types.iter().any(|allowed| match allowed.strip_suffix('*') {
+            Some(prefix) => mime.starts_with(prefix),
+            None => *allowed == mime || *allowed == base,
+        })
+    }
+
+    // whether the response would be compressed for a client that asked
+    fn is_eligible(&self, resp: &Response) -> bool {
+        let status = resp.status_code();
+        let has_body = status.allows_body() || status == StatusCode::NotModified;
+        if !has_body || status == StatusCode::PartialContent || resp.upgrade.is_some() {
+            return false;
+        }
+        if resp.headers().contains("Content-Encoding") {
+            return false;
+        }
+        let no_transform = resp
+            .headers()
+            .get_all("Cache-Control")
+            .flat_map(|v| v.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+        !no_transform && resp.headers().get("Content-Type").is_some_and(|t| self.compresses_type(t))
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, req: Request, next: Next) -> Response {
+        let encoding = encoding::negotiate(&req, &self.encodings);
+        let is_head = req.method == Method::HEAD;
+        let mut resp = next.run(req);
+        if !self.is_eligible(&resp) {
+            return resp;
+        }
+        // caches must not hand the compressed body to clients that can't take it
+        add_vary(&mut resp, "Accept-Encoding");
+
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => return resp,
+        };
+        // decided before the size so a 304 carries the tag of its 200
+        weaken_etag(&mut resp);
+        if resp.status_code() == StatusCode::NotModified {
+            return resp;
+        }
+        if is_head {
+            // the body is already gone, so go by the length it would have had
+            if resp.content_length().is_some_and(|len| len < self.min_size) {
+                return resp;
+            }
+            mark_encoded(&mut resp, encoding);
+            // the encoded length isn't known without compressing the body
+            resp.set_body(ResponseBody::stream(io::empty(), None));
+            resp.strip_body();
+            return resp;
+        }
+        let body = match resp.take_body() {
+            ResponseBody::Bytes(bytes) if (bytes.len() as u64) < self.min_size => ResponseBody::Bytes(bytes),
+            ResponseBody::Bytes(bytes) => match compress(encoding, &bytes) {
+                // not worth it for data that is already dense
+                Ok(compressed) if compressed.len() < bytes.len() => {
+                    mark_encoded(&mut resp, encoding);
+                    ResponseBody::Bytes(compressed)
+                }
+                _ => ResponseBody::Bytes(bytes),
+            },
+            ResponseBody::Stream { reader, len } if len.is_some_and(|len| len < self.min_size) => {
+                ResponseBody::Stream { reader, len }
+            }
+            ResponseBody::Stream { reader, .. } => {
+                mark_encoded(&mut resp, encoding);
+                ResponseBody::stream(CompressReader::new(reader, encoding), None)
+            }
+            ResponseBody::Empty => ResponseBody::Empty,
+        };
+        resp.set_body(body);
+        resp
+    }
+}
+
+// the bytes may differ from the identity body, so a strong validator no
+// longer holds
+fn weaken_etag(resp: &mut Response) {
+    if let Some(etag) = resp.headers().get("ETag").filter(|etag| !etag.starts_with("W/")) {
+        let weak = format!("W/{}", etag);
+        resp.set_header("ETag", &weak);
+    }
+}
+
+fn mark_encoded(resp: &mut Response, encoding: ContentEncoding) {
+    resp.set_header("Content-Encoding", encoding.as_str());
+    // ranges would apply to the wrong representation
+    resp.headers_mut().remove("Accept-Ranges");
+}
+
+fn compress(encoding: ContentEncoding, bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(encoding);
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+enum Encoder {
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: ContentEncoding) -> Self {
+        match encoding {
+            ContentEncoding::Brotli => Self::Brotli(Box::new(CompressorWriter::new(
+                Vec::new(),
+                CHUNK_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+            ContentEncoding::Gzip => Self::Gzip(GzEncoder::new(Vec::new(), Level::default())),
+            // "deflate" in HTTP means the zlib format (RFC 9110 8.4.1.2)
+            ContentEncoding::Deflate => Self::Deflate(ZlibEncoder::new(Vec::new(), Level::default())),
+        }
+    }
+
+    // the compressed bytes produced so far
+    fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            Self::Brotli(w) => w.get_mut(),
+            Self::Gzip(w) => w.get_mut(),
+            Self::Deflate(w) => w.get_mut(),
+        }
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Brotli(w) => Ok(w.into_inner()),
+            Self::Gzip(w) => w.finish(),
+            Self::Deflate(w) => w.finish(),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Brotli(w) => w.write(buf),
+            Self::Gzip(w) => w.write(buf),
+            Self::Deflate(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Brotli(w) => w.flush(),
+            Self::Gzip(w) => w.flush(),
+            Self::Deflate(w) => w.flush(),
+        }
+    }
+}
+
+// compresses a streamed body as it is read
+struct CompressReader<R> {
+    reader: R,
+    // None once the stream is finished
+    encoder: Option<Encoder>,
+    buf: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> CompressReader<R> {
+    fn new(reader: R, encoding: ContentEncoding) -> Self {
+        Self {
+            reader,
+            encoder: Some(Encoder::new(encoding)),
+            buf: vec![0; CHUNK_SIZE],
+            out: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for CompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            let encoder = match self.encoder.as_mut() {
+                Some(encoder) => encoder,
+                None => return Ok(0),
+            };
+            let n = self.reader.read(&mut self.buf)?;
+            self.out = if n == 0 {
+                self.encoder.take().expect("encoder is present").finish()?
+            } else {
+                encoder.write_all(&self.buf[..n])?;
+                // a stream may trickle, e.g. server-sent events, so each read
+                // is sent on rather than held back for a better ratio
+                encoder.flush()?;
+                std::mem::take(encoder.output())
+            };
+            self.pos = 0;
+        }
+        let n = (&self.out[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
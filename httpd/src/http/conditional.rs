@@ -0,0 +1,72 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{date, Method, Request};
+
+/// Whether the client's cached copy of a representation with this `etag`
+/// and `last_modified` is still current, so a GET or HEAD can be answered
+/// with 304 Not Modified (RFC 7232 3.2, 3.3 and 6).
+pub fn is_fresh(req: &Request, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+    if !matches!(req.method, Method::GET | Method::HEAD) {
+        return false;
+    }
+
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = req.headers.get_all("If-None-Match") {
+        return etag.is_some_and(|etag| {
+            if_none_match
+                .iter()
+                .any(|list| list_matches(list, |tag| weak_eq(tag, etag)))
+        });
+    }
+
+    let since = req.headers.get("If-Modified-Since").and_then(date::parse_http_date);
+    match (since, last_modified) {
+        (Some(since), Some(modified)) => truncate_to_secs(modified) <= since,
+        _ => false,
+    }
+}
+
+/// Weak comparison: the tags match even if either is weak (RFC 7232 2.3.2).
+pub fn weak_eq(a: &str, b: &str) -> bool {
+    opaque_tag(a) == opaque_tag(b)
+}
+
+/// Strong comparison: both tags must be strong and identical.
+pub fn strong_eq(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+// whether a `*` or comma separated entity-tag list has a tag matching `eq`
+fn list_matches(list: &str, eq: impl Fn(&str) -> bool) -> bool {
+    let list = list.trim();
+    if list == "*" {
+        return true;
+    }
+    let mut rest = list;
+    while let Some(start) = rest.find('"') {
+        // keep a W/ prefix that sits right before the opening quote
+        let weak = rest[..start].ends_with("W/");
+        let after = &rest[start + 1..];
+        let end = match after.find('"') {
+            Some(end) => end,
+            None => return false,
+        };
+        let tag = &rest[start..start + end + 2];
+        let matched = if weak { eq(&format!("W/{}", tag)) } else { eq(tag) };
+        if matched {
+            return true;
+        }
+        rest = &after[end + 1..];
+    }
+    false
+}
+
+fn opaque_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+// HTTP dates only have whole seconds
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
@@ -0,0 +1,217 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{date, Request, Response, ResponseBuilder};
+
+/// The cookies a request was sent with, borrowing from its `Cookie` headers.
+#[derive(Debug, Default)]
+pub struct Cookies<'buf> {
+    data: Vec<(&'buf str, &'buf str)>,
+}
+
+impl<'buf> Cookies<'buf> {
+    /// Returns the first cookie called `name`, browsers send the one with
+    /// the most specific path first.
+    pub fn get(&self, name: &str) -> Option<&'buf str> {
+        self.data
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'buf str, &'buf str)> + '_ {
+        self.data.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl<'buf> From<&'buf str> for Cookies<'buf> {
+    // a=1; b="two"
+    fn from(s: &'buf str) -> Self {
+        let data = s
+            .split(';')
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                Some((name, value)).filter(|(name, _)| !name.is_empty())
+            })
+            .collect();
+        Cookies { data }
+    }
+}
+
+impl<'buf> Request<'buf> {
+    /// Parses the request's `Cookie` headers.
+    pub fn cookies(&self) -> Cookies<'buf> {
+        let mut cookies = Cookies::default();
+        for header in self.headers.get_all("Cookie").unwrap_or_default() {
+            cookies.data.extend(Cookies::from(*header).data);
+        }
+        cookies
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+/// A `Set-Cookie` header (RFC 6265 4.1). The name and value are sent as
+/// given, so values that aren't plain cookie-octets should be encoded first.
+///
+/// ```ignore
+/// let cookie = SetCookie::new("theme", "dark")
+///     .path("/")
+///     .max_age(Duration::from_secs(3600))
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+/// Response::builder(StatusCode::Ok).cookie(&cookie).build()
+/// ```
+#[derive(Debug, Clone)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// A cookie that makes the browser delete `name`. Its path and domain
+    /// must match the ones the cookie was set with.
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(UNIX_EPOCH)
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// `SameSite::None` is only accepted by browsers on secure cookies.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Display for SetCookie {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", date::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl Response {
+    /// Adds a `Set-Cookie` header, keeping any cookies set before.
+    pub fn set_cookie(&mut self, cookie: &SetCookie) {
+        self.headers_mut().append("Set-Cookie", &cookie.to_string());
+    }
+}
+
+impl ResponseBuilder {
+    /// Adds a `Set-Cookie` header, keeping any cookies set before.
+    pub fn cookie(self, cookie: &SetCookie) -> Self {
+        self.append_header("Set-Cookie", &cookie.to_string())
+    }
+}
@@ -0,0 +1,211 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a time as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+/// (RFC 7231 7.1.1.1). Times before the epoch are clamped to it.
+pub fn fmt_http_date(time: SystemTime) -> String {
+    let t = DateTime::from(time);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        // 1970-01-01 was a Thursday
+        DAYS[(t.days % 7) as usize],
+        t.day,
+        MONTHS[t.month as usize - 1],
+        t.year,
+        t.hour,
+        t.min,
+        t.sec,
+    )
+}
+
+/// Formats a time the way the Common Log Format does, e.g.
+/// `06/Nov/1994:08:49:37 +0000`.
+pub fn fmt_clf_date(time: SystemTime) -> String {
+    let t = DateTime::from(time);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        t.day,
+        MONTHS[t.month as usize - 1],
+        t.year,
+        t.hour,
+        t.min,
+        t.sec,
+    )
+}
+
+/// Formats a time as RFC 3339 in UTC with millisecond precision, e.g.
+/// `1994-11-06T08:49:37.000Z`.
+pub fn fmt_rfc3339(time: SystemTime) -> String {
+    let t = DateTime::from(time);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        t.year, t.month, t.day, t.hour, t.min, t.sec, t.millis,
+    )
+}
+
+struct DateTime {
+    days: u64,
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u64,
+    min: u64,
+    sec: u64,
+    millis: u32,
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let days = secs / 86400;
+        let secs_of_day = secs % 86400;
+        let (year, month, day) = civil_from_days(days as i64);
+        Self {
+            days,
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            min: secs_of_day % 3600 / 60,
+            sec: secs_of_day % 60,
+            millis: since_epoch.subsec_millis(),
+        }
+    }
+}
+
+/// Parses an HTTP-date in any of the three formats recipients must accept
+/// (RFC 7231 7.1.1.1):
+///
+/// ```text
+/// Sun, 06 Nov 1994 08:49:37 GMT    IMF-fixdate
+/// Sunday, 06-Nov-94 08:49:37 GMT   obsolete RFC 850
+/// Sun Nov  6 08:49:37 1994         obsolete asctime
+/// ```
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    let (day, month, year, time) = if let Some((_, rest)) = s.split_once(", ") {
+        let rest = rest.strip_suffix(" GMT")?;
+        if rest.contains('-') {
+            // 06-Nov-94 08:49:37
+            let (date, time) = rest.split_once(' ')?;
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            let year: i64 = year.parse().ok()?;
+            // two digit years more than 50 years ahead are in the past (RFC 7231)
+            let year = if year < 70 { 2000 + year } else if year < 100 { 1900 + year } else { year };
+            (day.parse().ok()?, month, year, time)
+        } else {
+            // 06 Nov 1994 08:49:37
+            let mut parts = rest.split(' ');
+            let (day, month, year, time) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+            (day.parse().ok()?, month, year.parse().ok()?, time)
+        }
+    } else {
+        // Nov  6 08:49:37 1994
+        let mut parts = s.split_whitespace().skip(1);
+        let (month, day, time, year) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        (day.parse().ok()?, month, year.parse().ok()?, time)
+    };
+
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut time = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, min, sec) = (time.next()??, time.next()??, time.next()??);
+    // the grammar only has four digit years, which keeps the math below in range
+    if !(1..=9999).contains(&year) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days.checked_mul(86400)?.checked_add(hour * 3600 + min * 60 + sec)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+// days since 1970-01-01 to (year, month, day), from Howard Hinnant's
+// "chrono-Compatible Low-Level Date Algorithms", as is its inverse below
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn parses_all_three_formats() {
+        let expected = Some(at(784111777));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+    }
+
+    #[test]
+    fn two_digit_years_below_70_are_this_century() {
+        assert_eq!(parse_http_date("Thursday, 01-Jan-70 00:00:00 GMT"), Some(UNIX_EPOCH));
+        assert_eq!(parse_http_date("Saturday, 01-Jan-00 00:00:00 GMT"), Some(at(946684800)));
+    }
+
+    #[test]
+    fn round_trips_through_fmt_http_date() {
+        let time = at(1_700_000_000);
+        assert_eq!(parse_http_date(&fmt_http_date(time)), Some(time));
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for date in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{:?}", date);
+        }
+    }
+
+    #[test]
+    fn rejects_years_out_of_range_without_overflowing() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1969 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 10000 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 9223372036854775807 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 -9223372036854775808"), None);
+        assert!(parse_http_date("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+    }
+
+    #[test]
+    fn formats_dates() {
+        let time = at(784111777) + Duration::from_millis(5);
+        assert_eq!(fmt_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(fmt_clf_date(time), "06/Nov/1994:08:49:37 +0000");
+        assert_eq!(fmt_rfc3339(time), "1994-11-06T08:49:37.005Z");
+    }
+}
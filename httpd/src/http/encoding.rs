@@ -0,0 +1,81 @@
+use super::{Request, Response};
+
+/// A content coding applied to response bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentEncoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The name used in `Accept-Encoding` and `Content-Encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+}
+
+/// Picks the coding the client accepts most from `available`, which is in
+/// the server's order of preference for codings the client likes equally.
+/// None means the body should be sent as is.
+pub fn negotiate(req: &Request, available: &[ContentEncoding]) -> Option<ContentEncoding> {
+    let accepted = req.headers.get_all("Accept-Encoding")?;
+    let accepted = accepted
+        .iter()
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_coding)
+        .collect::<Vec<_>>();
+
+    let quality = |encoding: ContentEncoding| {
+        let named = accepted.iter().find(|(name, _)| {
+            name.eq_ignore_ascii_case(encoding.as_str())
+                || (encoding == ContentEncoding::Gzip && name.eq_ignore_ascii_case("x-gzip"))
+        });
+        named
+            .or_else(|| accepted.iter().find(|(name, _)| *name == "*"))
+            .map_or(0.0, |(_, q)| *q)
+    };
+
+    let mut best: Option<(ContentEncoding, f32)> = None;
+    for &encoding in available {
+        let q = quality(encoding);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+// adds `name` to the Vary header unless it is listed already
+pub(crate) fn add_vary(resp: &mut Response, name: &str) {
+    let listed = resp
+        .headers()
+        .get_all("Vary")
+        .flat_map(|v| v.split(','))
+        .any(|field| field.trim() == "*" || field.trim().eq_ignore_ascii_case(name));
+    if !listed {
+        resp.headers_mut().append("Vary", name);
+    }
+}
+
+// "gzip;q=0.8" -> ("gzip", 0.8), None if the weight is malformed
+fn parse_coding(item: &str) -> Option<(&str, f32)> {
+    let mut parts = item.split(';');
+    let name = parts.next()?.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut q = 1.0;
+    for param in parts {
+        if let Some((key, value)) = param.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("q") {
+                q = value.trim().parse().ok().filter(|q| (0.0..=1.0).contains(q))?;
+            }
+        }
+    }
+    Some((name, q))
+}
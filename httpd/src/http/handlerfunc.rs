@@ -1,3 +1,3 @@
 use super::{Request, Response};
 
-pub type HandlerFunc = Box<dyn Fn(Request) -> Response>;
\ No newline at end of file
+pub type HandlerFunc = Box<dyn Fn(Request) -> Response + Send + Sync>;
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use super::request::is_token_char;
+
+// header names compare case-insensitively (RFC 7230 3.2)
+#[derive(Debug, Clone, Copy)]
+struct HeaderName<'buf>(&'buf str);
+
+// unsized view of a header name, lets lookups borrow keys of any lifetime
+#[repr(transparent)]
+struct Name(str);
+
+impl Name {
+    fn new(s: &str) -> &Name {
+        // SAFETY: Name is a transparent wrapper around str
+        unsafe { &*(s as *const str as *const Name) }
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for b in self.0.bytes() {
+            state.write_u8(b.to_ascii_lowercase());
+        }
+        state.write_u8(0xff);
+    }
+}
+
+impl PartialEq for HeaderName<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        Name::new(self.0) == Name::new(other.0)
+    }
+}
+
+impl Eq for HeaderName<'_> {}
+
+impl Hash for HeaderName<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Name::new(self.0).hash(state)
+    }
+}
+
+impl Borrow<Name> for HeaderName<'_> {
+    fn borrow(&self) -> &Name {
+        Name::new(self.0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Headers<'buf> {
+    data: HashMap<HeaderName<'buf>, Vec<&'buf str>>,
+}
+
+impl<'buf> Headers<'buf> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &'buf str, value: &'buf str) {
+        self.data
+            .entry(HeaderName(name))
+            .and_modify(|vec| vec.push(value))
+            .or_insert_with(|| vec![value]);
+    }
+
+    /// Returns the first value received for `name`.
+    pub fn get(&self, name: &str) -> Option<&'buf str> {
+        self.get_all(name).and_then(|values| values.first().copied())
+    }
+
+    /// Returns every value received for `name`, in the order they appeared.
+    pub fn get_all(&self, name: &str) -> Option<&[&'buf str]> {
+        self.data.get(Name::new(name)).map(|values| &values[..])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.data.contains_key(Name::new(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'buf str, &[&'buf str])> {
+        self.data.iter().map(|(name, values)| (name.0, &values[..]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Owned, ordered headers for a response. Names are matched
+/// case-insensitively but sent as given.
+///
+/// Adding a header whose name isn't a token (RFC 9110 5.1), e.g. one with a
+/// colon, space or newline in it, panics.
+#[derive(Debug, Default, Clone)]
+pub struct HeaderMap {
+    data: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `name` to `value`, replacing every earlier value.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// Adds another value for `name`, e.g. for repeated `Set-Cookie` headers.
+    pub fn append(&mut self, name: &str, value: &str) {
+        // values are cleaned up when sent, but a name with a colon or a
+        // newline in it can't be made safe
+        assert!(
+            !name.is_empty() && name.bytes().all(is_token_char),
+            "invalid header name {:?}",
+            name
+        );
+        self.data.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.data.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.data
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.data.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
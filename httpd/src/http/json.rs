@@ -0,0 +1,106 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Request, Response, StatusCode};
+
+impl<'buf> Request<'buf> {
+    /// Parses the body as JSON. The request must be sent as
+    /// `application/json` or another `+json` type.
+    pub fn json<T: Deserialize<'buf>>(&self) -> Result<T, JsonError> {
+        let content_type = self.headers.get("Content-Type").unwrap_or("");
+        if !is_json(content_type) {
+            return Err(JsonError::UnsupportedMediaType);
+        }
+        serde_json::from_slice(self.body).map_err(JsonError::Malformed)
+    }
+}
+
+impl Response {
+    /// Serializes `value` as the body, with `Content-Type: application/json`.
+    pub fn json<T: Serialize + ?Sized>(status_code: StatusCode, value: &T) -> Response {
+        match serde_json::to_vec(value) {
+            Ok(body) => Response::builder(status_code)
+                .content_type("application/json")
+                .body(body)
+                .build(),
+            Err(e) => {
+                log::error!("Failed to serialize response: {}", e);
+                Response::new(StatusCode::InternalServerError, None)
+            }
+        }
+    }
+}
+
+/// Wraps a handler that takes the request body as a typed value. Requests
+/// that aren't JSON get 415 and malformed ones 400, without calling `func`:
+///
+/// ```ignore
+/// router.post("/users", json::handler(|_req, user: NewUser| {
+///     Response::json(StatusCode::Created, &create(user))
+/// }));
+/// ```
+pub fn handler<T, F>(func: F) -> impl Fn(Request) -> Response + Send + Sync
+where
+    T: DeserializeOwned,
+    F: Fn(Request, T) -> Response + Send + Sync,
+{
+    move |req| match req.json::<T>() {
+        Ok(value) => func(req, value),
+        Err(e) => e.into_response(),
+    }
+}
+
+// application/json, application/problem+json, ...
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    let (kind, subtype) = mime.split_once('/').unwrap_or(("", ""));
+    kind.eq_ignore_ascii_case("application")
+        && (subtype.eq_ignore_ascii_case("json") || subtype.to_ascii_lowercase().ends_with("+json"))
+}
+
+pub enum JsonError {
+    UnsupportedMediaType,
+    Malformed(serde_json::Error),
+}
+
+impl JsonError {
+    fn error(&self) -> &str {
+        match self {
+            Self::UnsupportedMediaType => "Expected A JSON Content Type",
+            Self::Malformed(_) => "Malformed JSON",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnsupportedMediaType => StatusCode::UnsupportedMediaType,
+            Self::Malformed(_) => StatusCode::BadRequest,
+        }
+    }
+
+    /// A JSON response describing the error, e.g. `{"error":"Malformed JSON: ..."}`.
+    pub fn into_response(self) -> Response {
+        Response::json(self.status_code(), &serde_json::json!({ "error": self.to_string() }))
+    }
+}
+
+impl Error for JsonError {}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Malformed(e) => write!(f, "{}: {}", self.error(), e),
+            _ => write!(f, "{}", self.error()),
+        }
+    }
+}
+
+impl Debug for JsonError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,54 @@
+use super::{Request, Response};
+
+/// Cross-cutting logic that runs around handlers.
+///
+/// A middleware may inspect or modify the request before calling
+/// `next.run(req)`, return its own response without calling it at all, or
+/// post-process the response that comes back. Closures taking
+/// `(Request, Next)` are middleware too.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, req: Request, next: Next) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, Next) -> Response + Send + Sync,
+{
+    fn handle(&self, req: Request, next: Next) -> Response {
+        self(req, next)
+    }
+}
+
+/// The rest of the chain after the current middleware.
+pub struct Next<'a> {
+    chain: &'a [Box<dyn Middleware>],
+    endpoint: &'a (dyn Fn(Request) -> Response + Sync),
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(chain: &'a [Box<dyn Middleware>], endpoint: &'a (dyn Fn(Request) -> Response + Sync)) -> Self {
+        Self { chain, endpoint }
+    }
+
+    pub fn run(self, req: Request) -> Response {
+        match self.chain.split_first() {
+            Some((middleware, chain)) => middleware.handle(req, Next { chain, ..self }),
+            None => (self.endpoint)(req),
+        }
+    }
+}
+
+/// Wraps a single handler in a middleware, for logic that only applies to
+/// some routes:
+///
+/// ```ignore
+/// router.get("/admin", middleware::wrap(require_auth, admin));
+/// ```
+pub fn wrap<M, H>(middleware: M, handler: H) -> impl Fn(Request) -> Response + Send + Sync
+where
+    M: Middleware + 'static,
+    H: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let chain: [Box<dyn Middleware>; 1] = [Box::new(middleware)];
+    move |req| Next::new(&chain, &handler).run(req)
+}
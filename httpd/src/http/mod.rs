@@ -1,15 +1,58 @@
 pub use request::Request;
 pub use method::Method;
 pub use query_string::QueryString;
+pub use headers::{Headers, HeaderMap};
+pub use body::{BodyReader, BodyError};
 pub use status_code::StatusCode;
-pub use response::Response;
+pub use response::{Response, ResponseBody, ResponseBuilder};
 pub use handlerfunc::HandlerFunc;
 pub use router::Router;
+pub use params::Params;
+pub use middleware::{Middleware, Next};
+pub use static_files::StaticFiles;
+pub use multipart::{Multipart, MultipartError, Part};
+#[cfg(feature = "json")]
+pub use json::JsonError;
+pub use cookie::{Cookies, SameSite, SetCookie};
+pub use extensions::Extensions;
+pub use upgrade::Upgraded;
+pub use sse::{Event, EventSender, EventStream};
+pub use encoding::ContentEncoding;
+#[cfg(feature = "compression")]
+pub use compression::Compression;
+#[cfg(feature = "websocket")]
+pub use websocket::{Message, WebSocket, WebSocketError};
+#[cfg(feature = "session")]
+pub use session::{MemoryStore, Session, SessionLayer, SessionStore};
 
 pub mod status_code;
 pub mod response;
 pub mod method;
 pub mod request;
 pub mod query_string;
+pub mod headers;
+pub mod body;
 pub mod handlerfunc;
-pub mod router;
\ No newline at end of file
+pub mod router;
+pub mod params;
+pub mod middleware;
+pub mod date;
+pub mod mime;
+pub mod static_files;
+pub mod conditional;
+pub mod range;
+pub mod urlencoded;
+pub mod multipart;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod cookie;
+pub mod extensions;
+pub mod upgrade;
+pub mod sse;
+pub mod encoding;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "session")]
+pub mod session;
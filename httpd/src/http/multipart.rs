@@ -0,0 +1,465 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    io::{self, Read},
+};
+
+use super::{Request, StatusCode};
+
+const DEFAULT_MAX_PART_SIZE: u64 = 8 * 1024 * 1024;
+const DEFAULT_MAX_TOTAL_SIZE: u64 = 32 * 1024 * 1024;
+const MAX_PART_HEAD_LEN: usize = 8 * 1024;
+const READ_SIZE: usize = 8 * 1024;
+
+/// One field or file of a `multipart/form-data` body.
+#[derive(Debug)]
+pub struct Part {
+    pub name: String,
+    /// Set for file uploads.
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// The data as text, for plain form fields.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum State {
+    Preamble,
+    PartHead,
+    Done,
+}
+
+/// Reads the parts of a `multipart/form-data` body one at a time (RFC 7578).
+///
+/// Only a reader passed to `new` is streamed, with just the current part
+/// held in memory. `from_request` parses a body the server has already
+/// read in full, so there `Server::max_body_size`, 1 MiB unless changed,
+/// bounds the whole upload and the part and total limits only matter when
+/// set below it. Upload routes can raise it to match with
+/// `Router::max_body_size`:
+///
+/// ```ignore
+/// router.post("/upload", |req| {
+///     let mut multipart = Multipart::from_request(&req)?.max_total_size(32 << 20);
+///     while let Some(part) = multipart.next_part()? {
+///         // part.name, part.filename, part.data ...
+///     }
+///     // ...
+/// });
+/// router.max_body_size(Method::POST, "/upload", 32 << 20);
+/// ```
+pub struct Multipart<R> {
+    reader: R,
+    // "\r\n--boundary"
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    eof: bool,
+    state: State,
+    max_part_size: u64,
+    max_total_size: u64,
+    total: u64,
+}
+
+impl<'buf> Multipart<&'buf [u8]> {
+    /// Parses the body of a request sent as `multipart/form-data`. The body
+    /// is already in memory, see above.
+    pub fn from_request(req: &Request<'buf>) -> Result<Self, MultipartError> {
+        let content_type = req.headers.get("Content-Type").ok_or(MultipartError::InvalidContentType)?;
+        let boundary = boundary(content_type).ok_or(MultipartError::InvalidContentType)?;
+        Ok(Self::new(req.body, &boundary))
+    }
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &str) -> Self {
+        Self {
+            reader,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // the first delimiter has no CRLF before it, pretending it does
+            // lets every delimiter be found the same way
+            buf: b"\r\n".to_vec(),
+            eof: false,
+            state: State::Preamble,
+            max_part_size: DEFAULT_MAX_PART_SIZE,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+            total: 0,
+        }
+    }
+
+    /// Largest allowed part, 8 MiB unless changed.
+    pub fn max_part_size(mut self, max_part_size: u64) -> Self {
+        self.max_part_size = max_part_size;
+        self
+    }
+
+    /// Largest allowed sum of all parts, 32 MiB unless changed.
+    pub fn max_total_size(mut self, max_total_size: u64) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    /// Returns the next part, or None after the closing delimiter.
+    pub fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.state == State::Preamble {
+            // whatever precedes the first delimiter is ignored
+            self.read_until_delimiter(|_| Ok(()))?;
+            self.state = State::PartHead;
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        // after a delimiter comes either "--" for the end, or the part's head
+        self.fill_to(2)?;
+        if self.buf.starts_with(b"--") {
+            self.state = State::Done;
+            return Ok(None);
+        }
+        let head = self.read_part_head()?;
+        let mut part = parse_part_head(&head)?;
+
+        let (max_part_size, max_total_size) = (self.max_part_size, self.max_total_size);
+        let mut data = Vec::new();
+        let mut total = self.total;
+        self.read_until_delimiter(|chunk| {
+            data.extend_from_slice(chunk);
+            total += chunk.len() as u64;
+            if data.len() as u64 > max_part_size {
+                return Err(MultipartError::PartTooLarge);
+            }
+            if total > max_total_size {
+                return Err(MultipartError::TooLarge);
+            }
+            Ok(())
+        })?;
+        self.total = total;
+        part.data = data;
+        Ok(Some(part))
+    }
+
+    // hands everything before the next delimiter to `sink` and consumes the
+    // delimiter itself
+    fn read_until_delimiter<F>(&mut self, mut sink: F) -> Result<(), MultipartError>
+    where
+        F: FnMut(&[u8]) -> Result<(), MultipartError>,
+    {
+        loop {
+            if let Some(i) = find(&self.buf, &self.delimiter) {
+                sink(&self.buf[..i])?;
+                self.buf.drain(..i + self.delimiter.len());
+                return Ok(());
+            }
+            // keep a tail that could be the start of a delimiter
+            let keep = self.delimiter.len() - 1;
+            if self.buf.len() > keep {
+                let n = self.buf.len() - keep;
+                sink(&self.buf[..n])?;
+                self.buf.drain(..n);
+            }
+            if self.fill()? == 0 {
+                return Err(MultipartError::Malformed);
+            }
+        }
+    }
+
+    fn read_part_head(&mut self) -> Result<Vec<u8>, MultipartError> {
+        loop {
+            if let Some(i) = find(&self.buf, b"\r\n\r\n") {
+                let head = self.buf[..i].to_vec();
+                self.buf.drain(..i + 4);
+                return Ok(head);
+            }
+            if self.buf.len() > MAX_PART_HEAD_LEN {
+                return Err(MultipartError::Malformed);
+            }
+            if self.fill()? == 0 {
+                return Err(MultipartError::Malformed);
+            }
+        }
+    }
+
+    fn fill_to(&mut self, len: usize) -> Result<(), MultipartError> {
+        while self.buf.len() < len {
+            if self.fill()? == 0 {
+                return Err(MultipartError::Malformed);
+            }
+        }
+        Ok(())
+    }
+
+    fn fill(&mut self) -> io::Result<usize> {
+        if self.eof {
+            return Ok(0);
+        }
+        let start = self.buf.len();
+        self.buf.resize(start + READ_SIZE, 0);
+        let result = loop {
+            match self.reader.read(&mut self.buf[start..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        match result {
+            Ok(n) => {
+                self.buf.truncate(start + n);
+                self.eof = n == 0;
+                Ok(n)
+            }
+            Err(e) => {
+                self.buf.truncate(start);
+                Err(e)
+            }
+        }
+    }
+}
+
+// the head starts with the rest of the delimiter line, which may hold
+// transport padding
+fn parse_part_head(head: &[u8]) -> Result<Part, MultipartError> {
+    let head = std::str::from_utf8(head).map_err(|_| MultipartError::Malformed)?;
+    let mut lines = head.split("\r\n");
+    if !lines.next().unwrap_or("").trim_matches([' ', '\t']).is_empty() {
+        return Err(MultipartError::Malformed);
+    }
+
+    let mut part = Part { name: String::new(), filename: None, content_type: None, data: Vec::new() };
+    let mut has_name = false;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(MultipartError::Malformed)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Disposition") {
+            let mut params = split_params(value);
+            if !params.next().is_some_and(|(kind, _)| kind.eq_ignore_ascii_case("form-data")) {
+                return Err(MultipartError::Malformed);
+            }
+            for (key, value) in params {
+                if key.eq_ignore_ascii_case("name") {
+                    part.name = value;
+                    has_name = true;
+                } else if key.eq_ignore_ascii_case("filename") {
+                    part.filename = Some(value);
+                }
+            }
+        } else if name.eq_ignore_ascii_case("Content-Type") {
+            part.content_type = Some(value.to_string());
+        }
+    }
+
+    if !has_name {
+        return Err(MultipartError::Malformed);
+    }
+    Ok(part)
+}
+
+// `form-data; name="field"; filename="a \"b\".txt"` into (key, value) pairs,
+// the first being the disposition type with an empty value
+fn split_params(s: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest.find(['=', ';']).unwrap_or(rest.len());
+        let key = rest[..key_end].trim().to_string();
+        rest = &rest[key_end..];
+        let Some(value) = rest.strip_prefix('=') else {
+            return Some((key, String::new()));
+        };
+
+        let value = value.trim_start();
+        let (value, after) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let mut unquoted = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => unquoted.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        _ => unquoted.push(c),
+                    }
+                }
+                (unquoted, &quoted[end..])
+            }
+            None => {
+                let end = value.find(';').unwrap_or(value.len());
+                (value[..end].trim().to_string(), &value[end..])
+            }
+        };
+        rest = after;
+        Some((key, value))
+    })
+}
+
+// the boundary parameter of a multipart/form-data content type
+fn boundary(content_type: &str) -> Option<String> {
+    let mut params = split_params(content_type);
+    let (mime, _) = params.next()?;
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, boundary)| boundary)
+        .filter(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+pub enum MultipartError {
+    InvalidContentType,
+    Malformed,
+    PartTooLarge,
+    TooLarge,
+    Io(io::Error),
+}
+
+impl MultipartError {
+    fn error(&self) -> &str {
+        match self {
+            Self::InvalidContentType => "Invalid Multipart Content Type",
+            Self::Malformed => "Malformed Multipart Body",
+            Self::PartTooLarge => "Multipart Part Too Large",
+            Self::TooLarge => "Multipart Body Too Large",
+            Self::Io(_) => "Multipart Read Failed",
+        }
+    }
+
+    /// Status code to answer with when the body could not be parsed.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidContentType => StatusCode::UnsupportedMediaType,
+            Self::PartTooLarge | Self::TooLarge => StatusCode::PayloadTooLarge,
+            Self::Malformed | Self::Io(_) => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl From<io::Error> for MultipartError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Error for MultipartError {}
+
+impl Display for MultipartError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Io(e) => write!(f, "{}: {}", self.error(), e),
+            _ => write!(f, "{}", self.error()),
+        }
+    }
+}
+
+impl Debug for MultipartError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello\r\n\
+        --XyZ  \r\n\
+        Content-Disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line one\r\n--Xy\r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    // hands out one byte per read, so every delimiter straddles reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn assert_parts<R: Read>(mut multipart: Multipart<R>) {
+        let title = multipart.next_part().unwrap().unwrap();
+        assert_eq!(title.name, "title");
+        assert!(!title.is_file());
+        assert_eq!(title.text(), Some("hello"));
+
+        let upload = multipart.next_part().unwrap().unwrap();
+        assert_eq!(upload.name, "upload");
+        assert_eq!(upload.filename.as_deref(), Some("a \"b\".txt"));
+        assert_eq!(upload.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(upload.data, b"line one\r\n--Xy");
+
+        assert!(multipart.next_part().unwrap().is_none());
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_fields_and_files() {
+        assert_parts(Multipart::new(BODY, "XyZ"));
+    }
+
+    #[test]
+    fn finds_delimiters_split_across_reads() {
+        assert_parts(Multipart::new(Trickle(BODY), "XyZ"));
+    }
+
+    #[test]
+    fn takes_the_boundary_from_the_content_type() {
+        assert_eq!(boundary("multipart/form-data; boundary=XyZ").as_deref(), Some("XyZ"));
+        assert_eq!(boundary("Multipart/Form-Data; charset=utf-8; boundary=\"a b\"").as_deref(), Some("a b"));
+        assert_eq!(boundary("multipart/mixed; boundary=XyZ"), None);
+        assert_eq!(boundary("multipart/form-data; boundary="), None);
+        assert_eq!(boundary(&format!("multipart/form-data; boundary={}", "x".repeat(71))), None);
+    }
+
+    #[test]
+    fn enforces_the_part_and_total_limits() {
+        let mut multipart = Multipart::new(BODY, "XyZ").max_part_size(10);
+        assert!(multipart.next_part().is_ok());
+        assert!(matches!(multipart.next_part(), Err(MultipartError::PartTooLarge)));
+
+        let mut multipart = Multipart::new(BODY, "XyZ").max_total_size(15);
+        assert!(multipart.next_part().is_ok());
+        assert!(matches!(multipart.next_part(), Err(MultipartError::TooLarge)));
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        for body in [
+            &b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nno closing delimiter"[..],
+            b"--XyZ\r\nContent-Disposition: attachment; name=\"a\"\r\n\r\nx\r\n--XyZ--",
+            b"--XyZ\r\nContent-Disposition: form-data\r\n\r\nx\r\n--XyZ--",
+            b"--XyZ\r\nno colon\r\n\r\nx\r\n--XyZ--",
+            b"no delimiter at all",
+        ] {
+            let mut multipart = Multipart::new(body, "XyZ");
+            assert!(matches!(multipart.next_part(), Err(MultipartError::Malformed)), "{:?}", body);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::borrow::Cow;
+
+/// Values captured from the path by a route's `:name` and `*name` segments,
+/// percent-decoded.
+#[derive(Debug, Default)]
+pub struct Params<'buf> {
+    data: Vec<(String, Cow<'buf, str>)>,
+}
+
+impl<'buf> Params<'buf> {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.data.iter().map(|(key, value)| (key.as_str(), value.as_ref()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, name: &str, value: Cow<'buf, str>) {
+        self.data.push((name.to_string(), value));
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.data.pop();
+    }
+}
@@ -1,13 +1,46 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
-#[derive(Debug)]
+use super::urlencoded;
+
+/// Decoded `application/x-www-form-urlencoded` data, from a query string or
+/// a form body. Keys and values borrow from the request unless they had to
+/// be decoded.
+#[derive(Debug, Default)]
 pub struct QueryString<'buf> {
-    data: HashMap<&'buf str, Vec<&'buf str>>,
+    raw: &'buf str,
+    data: HashMap<Cow<'buf, str>, Vec<Cow<'buf, str>>>,
 }
 
 impl<'buf> QueryString<'buf> {
-    pub fn get(&self, key: &str) -> Option<&Vec<&str>> {
-        self.data.get(key)
+    /// Returns the first value for `key`, empty for keys given without `=`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).and_then(|values| values.first()).map(|v| v.as_ref())
+    }
+
+    /// Returns every value for `key`, in the order they appeared.
+    pub fn get_all(&self, key: &str) -> Option<&[Cow<'buf, str>]> {
+        self.data.get(key).map(|values| &values[..])
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[Cow<'buf, str>])> {
+        self.data.iter().map(|(key, values)| (key.as_ref(), &values[..]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The data as sent, still encoded.
+    pub fn as_str(&self) -> &'buf str {
+        self.raw
     }
 }
 
@@ -15,18 +48,12 @@ impl<'buf> From<&'buf str> for QueryString<'buf> {
     fn from(s: &'buf str) -> Self {
         let mut data = HashMap::new();
 
-        for kv in s.split('&') {
-            if let Some(i) = kv.find('=') {
-                let key = &kv[..i];
-                let val = &kv[i + 1..];
-                println!("{} {} {} {}", key, val, i, kv.len());
-
-                data.entry(key)
-                    .and_modify(|vec: &mut Vec<&'buf str>| vec.push(val))
-                    .or_insert(vec![val]);
-            }
+        for (key, val) in urlencoded::parse(s) {
+            data.entry(key)
+                .or_insert_with(Vec::new)
+                .push(val);
         }
 
-        QueryString { data }
+        QueryString { raw: s, data }
     }
 }
@@ -0,0 +1,294 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+use super::{conditional, date, Method, Request, Response, ResponseBody, StatusCode};
+
+// more ranges than this in one request is more likely abuse than a real client
+const MAX_RANGES: usize = 16;
+
+/// An inclusive range of byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    // an inclusive range always holds at least one byte
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeError {
+    /// The header is malformed or uses another unit, so it is ignored.
+    Invalid,
+    /// None of the ranges overlap the representation: 416.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header such as `bytes=0-99,200-,-50` against a
+/// representation of `len` bytes (RFC 7233 2.1). Ranges past the end are
+/// clipped and overlapping ones merged.
+pub fn parse_range(header: &str, len: u64) -> Result<Vec<ByteRange>, RangeError> {
+    let specs = header
+        .trim()
+        .strip_prefix("bytes=")
+        .ok_or(RangeError::Invalid)?;
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (first, last) = spec.split_once('-').ok_or(RangeError::Invalid)?;
+        let range = match (first, last) {
+            // -500: the last 500 bytes
+            ("", suffix) => {
+                let suffix: u64 = suffix.parse().map_err(|_| RangeError::Invalid)?;
+                if suffix == 0 || len == 0 {
+                    continue;
+                }
+                ByteRange { start: len.saturating_sub(suffix), end: len - 1 }
+            }
+            (first, last) => {
+                let start: u64 = first.parse().map_err(|_| RangeError::Invalid)?;
+                let end = match last {
+                    "" => u64::MAX,
+                    last => last.parse().map_err(|_| RangeError::Invalid)?,
+                };
+                if end < start {
+                    return Err(RangeError::Invalid);
+                }
+                if start >= len {
+                    continue;
+                }
+                ByteRange { start, end: end.min(len - 1) }
+            }
+        };
+        ranges.push(range);
+    }
+
+    if ranges.is_empty() {
+        return Err(RangeError::Unsatisfiable);
+    }
+    if ranges.len() > MAX_RANGES {
+        return Err(RangeError::Invalid);
+    }
+
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Ok(merged)
+}
+
+/// Serves `reader`, a representation of `len` bytes, as the body of `resp`,
+/// honouring the request's `Range` and `If-Range` headers.
+///
+/// `resp` should carry the headers of the full 200 response. It comes back
+/// unchanged apart from the body if no range applies, as 206 Partial Content
+/// (multipart/byteranges for several ranges), or as 416 Range Not
+/// Satisfiable.
+pub fn serve_ranges<R>(req: &Request, mut resp: Response, reader: R, len: u64) -> Response
+where
+    R: Read + Seek + Send + 'static,
+{
+    resp.set_header("Accept-Ranges", "bytes");
+
+    let header = match req.headers.get("Range") {
+        Some(header) if req.method == Method::GET && if_range_matches(req, &resp) => header,
+        _ => {
+            resp.set_body(ResponseBody::stream(reader, Some(len)));
+            return resp;
+        }
+    };
+
+    match parse_range(header, len) {
+        Ok(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            let mut reader = reader;
+            if let Err(e) = reader.seek(SeekFrom::Start(range.start)) {
+                log::error!("Failed to seek for range: {}", e);
+                return Response::new(StatusCode::InternalServerError, None);
+            }
+            resp.set_status_code(StatusCode::PartialContent);
+            resp.set_header("Content-Range", &format!("bytes {}-{}/{}", range.start, range.end, len));
+            resp.set_body(ResponseBody::stream(reader.take(range.len()), Some(range.len())));
+            resp
+        }
+        Ok(ranges) => {
+            let content_type = resp.headers().get("Content-Type").map(String::from);
+            let boundary = boundary();
+            let body = MultipartRanges::new(reader, &ranges, len, content_type.as_deref(), &boundary);
+            let body_len = body.len();
+            resp.set_status_code(StatusCode::PartialContent);
+            resp.set_header("Content-Type", &format!("multipart/byteranges; boundary={}", boundary));
+            resp.set_body(ResponseBody::stream(body, Some(body_len)));
+            resp
+        }
+        Err(RangeError::Unsatisfiable) => {
+            let mut resp = Response::new(StatusCode::RangeNotSatisfiable, None);
+            resp.set_header("Content-Range", &format!("bytes */{}", len));
+            resp
+        }
+        Err(RangeError::Invalid) => {
+            resp.set_body(ResponseBody::stream(reader, Some(len)));
+            resp
+        }
+    }
+}
+
+// If-Range only lets the range through while the validator is current,
+// otherwise the whole representation is sent (RFC 7233 3.2)
+fn if_range_matches(req: &Request, resp: &Response) -> bool {
+    let if_range = match req.headers.get("If-Range") {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return resp
+            .headers()
+            .get("ETag")
+            .is_some_and(|etag| conditional::strong_eq(if_range, etag));
+    }
+    let last_modified = resp.headers().get("Last-Modified").and_then(date::parse_http_date);
+    last_modified.is_some() && last_modified == date::parse_http_date(if_range)
+}
+
+fn boundary() -> String {
+    // RandomState is seeded randomly per process and per instance
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+enum Part {
+    Bytes(Cursor<Vec<u8>>),
+    Range { start: u64, remaining: u64, seeked: bool },
+}
+
+// a multipart/byteranges body read lazily from the underlying reader
+struct MultipartRanges<R> {
+    reader: R,
+    parts: Vec<Part>,
+    current: usize,
+    len: u64,
+}
+
+impl<R: Read + Seek> MultipartRanges<R> {
+    fn new(reader: R, ranges: &[ByteRange], total: u64, content_type: Option<&str>, boundary: &str) -> Self {
+        let mut parts = Vec::new();
+        for range in ranges {
+            let mut head = format!("\r\n--{}\r\n", boundary);
+            if let Some(content_type) = content_type {
+                head.push_str(&format!("Content-Type: {}\r\n", content_type));
+            }
+            head.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n\r\n", range.start, range.end, total));
+            parts.push(Part::Bytes(Cursor::new(head.into_bytes())));
+            parts.push(Part::Range { start: range.start, remaining: range.len(), seeked: false });
+        }
+        parts.push(Part::Bytes(Cursor::new(format!("\r\n--{}--\r\n", boundary).into_bytes())));
+
+        let len = parts
+            .iter()
+            .map(|part| match part {
+                Part::Bytes(bytes) => bytes.get_ref().len() as u64,
+                Part::Range { remaining, .. } => *remaining,
+            })
+            .sum();
+        Self { reader, parts, current: 0, len }
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl<R: Read + Seek> Read for MultipartRanges<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(part) = self.parts.get_mut(self.current) {
+            let n = match part {
+                Part::Bytes(bytes) => bytes.read(buf)?,
+                Part::Range { remaining: 0, .. } => 0,
+                Part::Range { start, remaining, seeked } => {
+                    if !*seeked {
+                        self.reader.seek(SeekFrom::Start(*start))?;
+                        *seeked = true;
+                    }
+                    let max = (*remaining).min(buf.len() as u64) as usize;
+                    let n = self.reader.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *remaining -= n as u64;
+                    n
+                }
+            };
+            if n > 0 {
+                return Ok(n);
+            }
+            self.current += 1;
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn parses_each_kind_of_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(vec![range(0, 99)]));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(vec![range(900, 999)]));
+        assert_eq!(parse_range("bytes=-50", 1000), Ok(vec![range(950, 999)]));
+    }
+
+    #[test]
+    fn clips_ranges_past_the_end() {
+        assert_eq!(parse_range("bytes=500-5000", 1000), Ok(vec![range(500, 999)]));
+        assert_eq!(parse_range("bytes=-5000", 1000), Ok(vec![range(0, 999)]));
+    }
+
+    #[test]
+    fn sorts_and_merges_overlapping_and_adjacent_ranges() {
+        assert_eq!(
+            parse_range("bytes=500-599, 0-9,10-19,550-700", 1000),
+            Ok(vec![range(0, 19), range(500, 700)])
+        );
+    }
+
+    #[test]
+    fn skips_ranges_that_start_past_the_end() {
+        assert_eq!(parse_range("bytes=2000-,0-0", 1000), Ok(vec![range(0, 0)]));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse_range("bytes=-10", 0), Err(RangeError::Unsatisfiable));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for header in ["items=0-1", "bytes=1", "bytes=a-b", "bytes=5-1", "bytes=-", "bytes=0-1-2"] {
+            assert_eq!(parse_range(header, 1000), Err(RangeError::Invalid), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_ranges() {
+        let specs = (0..=MAX_RANGES).map(|i| format!("{0}-{0}", i * 2)).collect::<Vec<_>>();
+        let header = format!("bytes={}", specs.join(","));
+        assert_eq!(parse_range(&header, 1000), Err(RangeError::Invalid));
+    }
+}
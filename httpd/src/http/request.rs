@@ -1,6 +1,7 @@
 use super::method::{Method, MethodError};
-use super::QueryString;
+use super::{urlencoded, Extensions, Headers, Params, QueryString};
 use std::{
+    borrow::Cow,
     convert::TryFrom,
     error::Error,
     fmt::{Display, Debug, Formatter, Result as FmtResult},
@@ -9,21 +10,45 @@ use std::{
 
 #[derive(Debug)]
 pub struct Request<'buf> {
-    pub path: &'buf str,
+    /// The percent-decoded path.
+    pub path: Cow<'buf, str>,
+    /// The path as sent, without the query string.
+    pub raw_path: &'buf str,
     pub query_str: Option<QueryString<'buf>>,
     pub method: Method,
+    pub headers: Headers<'buf>,
+    pub body: &'buf [u8],
+    pub params: Params<'buf>,
+    /// Values attached by middleware.
+    pub extensions: Extensions,
+}
+
+impl<'buf> Request<'buf> {
+    /// Parses an `application/x-www-form-urlencoded` body, or returns None
+    /// if the request has another content type.
+    pub fn form(&self) -> Option<QueryString<'buf>> {
+        let content_type = self.headers.get("Content-Type")?;
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        if !mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return None;
+        }
+        // the format is ASCII, anything else was percent-encoded
+        std::str::from_utf8(self.body).ok().map(QueryString::from)
+    }
 }
 
 impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
     type Error = ParseError;
 
     // GET / HTTP/1.1
+    // Host: localhost:8080
+    // Accept: */*
     fn try_from(buf: &'buf [u8]) -> Result<Request<'buf>, Self::Error> {
-        let req = std::str::from_utf8(&buf)?;
-        
-        let (method, req) = get_next_token(req).ok_or(ParseError::InvalidRequest)?;
-        let (mut path, req) = get_next_token(req).ok_or(ParseError::InvalidRequest)?;
-        let (protocol, _) = get_next_token(req).ok_or(ParseError::InvalidRequest)?;
+        let req = std::str::from_utf8(buf)?;
+
+        let (request_line, mut req) = get_next_line(req).ok_or(ParseError::InvalidRequest)?;
+        let (method, request_line) = get_next_token(request_line).ok_or(ParseError::InvalidRequest)?;
+        let (mut path, protocol) = get_next_token(request_line).ok_or(ParseError::InvalidRequest)?;
 
         if protocol != "HTTP/1.1" {
             return Err(ParseError::InvalidProtocol);
@@ -35,21 +60,65 @@ impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
             path = &path[..i];
         }
 
+        let mut headers = Headers::new();
+        loop {
+            let (line, rest) = get_next_line(req).ok_or(ParseError::InvalidRequest)?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = parse_header_line(line)?;
+            headers.insert(name, value);
+            req = rest;
+        }
+
         Ok(Self {
-            path,
+            path: urlencoded::percent_decode(path),
+            raw_path: path,
             query_str,
             method,
+            headers,
+            body: &[],
+            params: Params::default(),
+            extensions: Extensions::new(),
         })
     }
 }
 
 fn get_next_token(request: &str) -> Option<(&str, &str)> {
-    for (i, c) in request.chars().enumerate() {
-        if c == ' ' || c == '\r' {
-            return Some((&request[..i], &request[i + 1..]));
-        }
+    request
+        .find(' ')
+        .map(|i| (&request[..i], &request[i + 1..]))
+}
+
+// lines end in CRLF, but a bare LF is tolerated (RFC 7230 3.5)
+fn get_next_line(request: &str) -> Option<(&str, &str)> {
+    let i = request.find('\n')?;
+    let line = &request[..i];
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    Some((line, &request[i + 1..]))
+}
+
+// Content-Type: text/html
+fn parse_header_line(line: &str) -> Result<(&str, &str), ParseError> {
+    // obsolete line folding is rejected (RFC 7230 3.2.4)
+    if line.starts_with([' ', '\t']) {
+        return Err(ParseError::InvalidHeader);
+    }
+    let i = line.find(':').ok_or(ParseError::InvalidHeader)?;
+    let name = &line[..i];
+    let value = line[i + 1..].trim_matches([' ', '\t']);
+
+    if name.is_empty() || !name.bytes().all(is_token_char) {
+        return Err(ParseError::InvalidHeaderName);
+    }
+    if value.bytes().any(|b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+        return Err(ParseError::InvalidHeaderValue);
     }
-    None
+    Ok((name, value))
+}
+
+pub(crate) fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
 }
 
 pub enum ParseError {
@@ -57,6 +126,9 @@ pub enum ParseError {
     InvalidEnconding,
     InvalidProtocol,
     InvalidMethod,
+    InvalidHeader,
+    InvalidHeaderName,
+    InvalidHeaderValue,
 }
 
 impl ParseError {
@@ -66,6 +138,9 @@ impl ParseError {
             Self::InvalidEnconding => "Invalid Enconding",
             Self::InvalidProtocol => "Invalid Protocol",
             Self::InvalidMethod => "Invalid Method",
+            Self::InvalidHeader => "Invalid Header",
+            Self::InvalidHeaderName => "Invalid Header Name",
+            Self::InvalidHeaderValue => "Invalid Header Value",
         }
     }
 }
@@ -97,6 +172,9 @@ impl Debug for ParseError {
             Self::InvalidEnconding => write!(f, "Invalid Enconding"),
             Self::InvalidProtocol => write!(f, "Invalid Protocol"),
             Self::InvalidMethod => write!(f, "Invalid Method"),
+            Self::InvalidHeader => write!(f, "Invalid Header"),
+            Self::InvalidHeaderName => write!(f, "Invalid Header Name"),
+            Self::InvalidHeaderValue => write!(f, "Invalid Header Value"),
         }
     }
 }
\ No newline at end of file
@@ -1,52 +1,381 @@
-use std::{io::Write, fmt::Display};
+use std::{
+    fmt::{Debug, Display, Formatter, Write as FmtWrite},
+    io::{self, Read, Write},
+    time::SystemTime,
+};
 
-use super::StatusCode;
+use super::{conditional, date, upgrade::OnUpgrade, HeaderMap, Request, StatusCode};
+
+const SERVER: &str = concat!("httpd/", env!("CARGO_PKG_VERSION"));
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// What follows the response head.
+pub enum ResponseBody {
+    Empty,
+    Bytes(Vec<u8>),
+    /// Read and sent as it goes. Without a known length the body is sent
+    /// with chunked transfer encoding.
+    Stream {
+        reader: Box<dyn Read + Send>,
+        len: Option<u64>,
+    },
+}
+
+impl ResponseBody {
+    pub fn stream(reader: impl Read + Send + 'static, len: Option<u64>) -> Self {
+        Self::Stream { reader: Box::new(reader), len }
+    }
+
+    // None when the length is only known once the stream ends
+    fn len(&self) -> Option<u64> {
+        match self {
+            Self::Empty => Some(0),
+            Self::Bytes(bytes) => Some(bytes.len() as u64),
+            Self::Stream { len, .. } => *len,
+        }
+    }
+}
+
+impl Debug for ResponseBody {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty"),
+            Self::Bytes(bytes) => write!(f, "Bytes({:?})", String::from_utf8_lossy(bytes)),
+            Self::Stream { len, .. } => write!(f, "Stream {{ len: {:?} }}", len),
+        }
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(s: String) -> Self {
+        Self::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&str> for ResponseBody {
+    fn from(s: &str) -> Self {
+        Self::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for ResponseBody {
+    fn from(bytes: &[u8]) -> Self {
+        Self::Bytes(bytes.to_vec())
+    }
+}
 
 #[derive(Debug)]
 struct ResponseHeader {
     status_code: StatusCode,
-    content_length: u64,
+    content_length: Option<u64>,
+    headers: HeaderMap,
 }
 
 impl Display for ResponseHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let content_length_str = self.content_length.to_string();
-        let content_length_fmt = ["Content-Length: ", &content_length_str[..]].concat();
-        write!(
-            f, "{}\r\n{}",
-            self.status_code,
-            content_length_fmt,
-        )
+        write!(f, "{}", self.status_code)?;
+        if self.status_code.allows_body() {
+            match self.content_length {
+                Some(len) => write!(f, "\r\nContent-Length: {}", len)?,
+                None => write!(f, "\r\nTransfer-Encoding: chunked")?,
+            }
+        }
+        if !self.headers.contains("Date") {
+            write!(f, "\r\nDate: {}", date::fmt_http_date(SystemTime::now()))?;
+        }
+        if !self.headers.contains("Server") {
+            write!(f, "\r\nServer: {}", SERVER)?;
+        }
+        for (name, value) in self.headers.iter() {
+            // the framing written above is the only one that matches the body,
+            // a second one would let the client read it differently
+            if name.eq_ignore_ascii_case("Content-Length") || name.eq_ignore_ascii_case("Transfer-Encoding") {
+                continue;
+            }
+            // a 304 has no body for the type to describe, see `into_not_modified`
+            if self.status_code == StatusCode::NotModified && name.eq_ignore_ascii_case("Content-Type") {
+                continue;
+            }
+            // a stray newline would let a value inject headers of its own
+            let value = value.replace(['\r', '\n'], " ");
+            write!(f, "\r\n{}: {}", name, value)?;
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 pub struct Response {
     response_header: ResponseHeader,
-    body: Option<String>,
+    body: ResponseBody,
+    pub(crate) upgrade: Option<OnUpgrade>,
 }
 
 impl Response {
     pub fn new(status_code: StatusCode, body: Option<String>) -> Self {
-        let content_length: u64 = match &body {
-            Some(b) => b.len() as u64,
-            None => 0
-        };
+        Self::with_body(status_code, body.map_or(ResponseBody::Empty, ResponseBody::from))
+    }
+
+    pub fn with_body(status_code: StatusCode, body: impl Into<ResponseBody>) -> Self {
+        let body = body.into();
+        let content_length = body.len();
+        let response_header = ResponseHeader { status_code, content_length, headers: HeaderMap::new() };
+        Response { response_header, body, upgrade: None }
+    }
+
+    pub fn builder(status_code: StatusCode) -> ResponseBuilder {
+        ResponseBuilder {
+            status_code,
+            headers: HeaderMap::new(),
+            body: ResponseBody::Empty,
+        }
+    }
+
+    /// Sets a header, replacing any earlier value with the same name.
+    /// `Content-Length` and `Transfer-Encoding` follow from the body, so
+    /// values set for them are not sent.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.response_header.headers.insert(name, value);
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.response_header.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.response_header.headers
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.response_header.status_code
+    }
+
+    pub fn set_status_code(&mut self, status_code: StatusCode) {
+        self.response_header.status_code = status_code;
+    }
+
+    // answers to HEAD keep the Content-Length of the body they leave out
+    pub(crate) fn strip_body(&mut self) {
+        self.body = ResponseBody::Empty;
+    }
+
+    pub fn body(&self) -> &ResponseBody {
+        &self.body
+    }
+
+    /// The length sent in `Content-Length`, None for a chunked body. Answers
+    /// to HEAD keep the length of the body they leave out.
+    pub fn content_length(&self) -> Option<u64> {
+        self.response_header.content_length
+    }
+
+    // leaves an empty body behind, without touching the Content-Length
+    pub(crate) fn take_body(&mut self) -> ResponseBody {
+        std::mem::replace(&mut self.body, ResponseBody::Empty)
+    }
 
-        let response_header = ResponseHeader { status_code, content_length };
-        Response { response_header, body }
+    pub fn set_body(&mut self, body: impl Into<ResponseBody>) {
+        self.body = body.into();
+        self.response_header.content_length = self.body.len();
     }
 
-    pub fn send(&self, stream: &mut impl Write) -> std::io::Result<()> {
-        let body = match &self.body {
-            Some(s) => s,
-            None => "",
+    /// Whether `req` already has an up-to-date copy of this response, going
+    /// by its `ETag` and `Last-Modified` headers.
+    pub fn is_fresh_for(&self, req: &Request) -> bool {
+        let last_modified = self.headers().get("Last-Modified").and_then(date::parse_http_date);
+        conditional::is_fresh(req, self.headers().get("ETag"), last_modified)
+    }
+
+    /// Turns this into a 304 Not Modified that keeps the caching headers but
+    /// drops the body and the headers describing it. `Content-Type` is kept
+    /// so middleware can still tell what was not modified, but not sent.
+    pub fn into_not_modified(mut self) -> Response {
+        self.set_status_code(StatusCode::NotModified);
+        for name in ["Content-Language", "Content-Disposition"] {
+            self.response_header.headers.remove(name);
+        }
+        self.set_body(ResponseBody::Empty);
+        self
+    }
+
+    /// Asks the server to close the connection once this response is sent.
+    pub fn close_connection(&mut self) {
+        self.set_header("Connection", "close");
+    }
+
+    pub fn closes_connection(&self) -> bool {
+        self.headers()
+            .get_all("Connection")
+            .flat_map(|v| v.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("close"))
+    }
+
+    /// Writes the response out and returns how many body bytes were sent.
+    /// A streamed body is consumed in the process.
+    pub fn send(&mut self, stream: &mut impl Write) -> io::Result<u64> {
+        // the head goes out in one write rather than one per header
+        let mut head = String::new();
+        write!(head, "HTTP/1.1 {}\r\n\r\n", self.response_header).expect("writing to a String");
+        stream.write_all(head.as_bytes())?;
+
+        let sent = match self.take_body() {
+            _ if !self.status_code().allows_body() => 0,
+            ResponseBody::Empty => 0,
+            ResponseBody::Bytes(bytes) => {
+                stream.write_all(&bytes)?;
+                bytes.len() as u64
+            }
+            ResponseBody::Stream { reader, len: Some(len) } => {
+                let sent = io::copy(&mut reader.take(len), stream)?;
+                if sent < len {
+                    // the client is waiting for bytes that will never come
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                sent
+            }
+            ResponseBody::Stream { reader, len: None } => write_chunked(reader, stream)?,
         };
-        write!(
-            stream,
-            "HTTP/1.1 {}\r\n\r\n{}",
-            self.response_header,
-            body
-        )
+        stream.flush()?;
+        Ok(sent)
+    }
+}
+
+// returns the payload bytes sent, without the chunk framing
+fn write_chunked(mut reader: impl Read, stream: &mut impl Write) -> io::Result<u64> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE + 16);
+    let mut sent = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        chunk.clear();
+        write!(chunk, "{:x}\r\n", n)?;
+        chunk.extend_from_slice(&buf[..n]);
+        chunk.extend_from_slice(b"\r\n");
+        stream.write_all(&chunk)?;
+        // a stream may trickle, so don't sit on what has been read so far
+        stream.flush()?;
+        sent += n as u64;
+    }
+    stream.write_all(b"0\r\n\r\n")?;
+    Ok(sent)
+}
+
+/// Builds a response header by header:
+///
+/// ```ignore
+/// Response::builder(StatusCode::Ok)
+///     .header("Content-Type", "text/html")
+///     .body("<h1>Hello</h1>")
+///     .build()
+/// ```
+#[derive(Debug)]
+pub struct ResponseBuilder {
+    status_code: StatusCode,
+    headers: HeaderMap,
+    body: ResponseBody,
+}
+
+impl ResponseBuilder {
+    /// Sets a header, replacing any earlier value with the same name, see
+    /// `Response::set_header`.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Adds a header without replacing earlier values with the same name.
+    pub fn append_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn content_type(self, content_type: &str) -> Self {
+        self.header("Content-Type", content_type)
+    }
+
+    /// Sets the entity-tag, which must include its quotes, e.g. `"\"v1\""`.
+    pub fn etag(self, etag: &str) -> Self {
+        self.header("ETag", etag)
+    }
+
+    pub fn last_modified(self, time: SystemTime) -> Self {
+        self.header("Last-Modified", &date::fmt_http_date(time))
+    }
+
+    pub fn cache_control(self, cache_control: &str) -> Self {
+        self.header("Cache-Control", cache_control)
+    }
+
+    pub fn body(mut self, body: impl Into<ResponseBody>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Streams the body from `reader`, chunked unless `len` is known.
+    pub fn stream(self, reader: impl Read + Send + 'static, len: Option<u64>) -> Self {
+        self.body(ResponseBody::stream(reader, len))
+    }
+
+    pub fn build(self) -> Response {
+        let mut resp = Response::with_body(self.status_code, self.body);
+        resp.response_header.headers = self.headers;
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(mut resp: Response) -> String {
+        let mut out = Vec::new();
+        resp.send(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn framing_set_by_the_handler_is_not_sent() {
+        let mut resp = Response::new(StatusCode::Ok, Some("hello".to_string()));
+        resp.set_header("Content-Length", "100");
+        resp.headers_mut().append("transfer-encoding", "chunked");
+        let out = sent(resp);
+        assert_eq!(out.matches("Content-Length").count(), 1, "{}", out);
+        assert!(out.contains("\r\nContent-Length: 5\r\n"), "{}", out);
+        assert!(!out.to_ascii_lowercase().contains("transfer-encoding"), "{}", out);
+        assert!(out.ends_with("\r\n\r\nhello"), "{}", out);
+    }
+
+    #[test]
+    fn streams_of_unknown_length_are_chunked() {
+        let resp = Response::builder(StatusCode::Ok).stream(&b"hello"[..], None).build();
+        let out = sent(resp);
+        assert!(out.contains("\r\nTransfer-Encoding: chunked\r\n"), "{}", out);
+        assert!(!out.contains("Content-Length"), "{}", out);
+        assert!(out.ends_with("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"), "{}", out);
+    }
+
+    #[test]
+    fn not_modified_keeps_validators_but_not_the_content_type() {
+        let resp = Response::builder(StatusCode::Ok)
+            .content_type("text/plain")
+            .etag("\"v1\"")
+            .body("hello")
+            .build()
+            .into_not_modified();
+        let out = sent(resp);
+        assert!(out.starts_with("HTTP/1.1 304 Not Modified\r\n"), "{}", out);
+        assert!(out.contains("\r\nETag: \"v1\""), "{}", out);
+        assert!(!out.contains("Content-Type") && !out.contains("Content-Length"), "{}", out);
     }
 }
@@ -1,27 +1,333 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
-use super::{HandlerFunc, Request, Response, StatusCode};
+use super::{urlencoded, HandlerFunc, Method, Middleware, Next, Params, Request, Response, StatusCode, StaticFiles};
 
+// ordered so the Allow header lists methods consistently
+type Handlers = BTreeMap<Method, Route>;
 
+struct Route {
+    func: HandlerFunc,
+    // replaces `Server::max_body_size` for this route
+    max_body_size: Option<u64>,
+}
+
+// One path segment of the route tree. Lookups prefer a static segment, then a
+// `:param`, then a `*catch_all`, backtracking when a branch has no handler.
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    param: Option<(String, Box<Node>)>,
+    catch_all: Option<(String, Handlers)>,
+    handlers: Handlers,
+}
+
+impl Node {
+    fn insert(&mut self, method: Method, url: &str, mut segments: std::str::Split<'_, char>, route: Route) {
+        let segment = match segments.next() {
+            Some(segment) => segment,
+            None => {
+                self.handlers.insert(method, route);
+                return;
+            }
+        };
+
+        if let Some(name) = segment.strip_prefix(':') {
+            assert!(!name.is_empty(), "route {} has an unnamed parameter", url);
+            let (existing, child) = self
+                .param
+                .get_or_insert_with(|| (name.to_string(), Box::default()));
+            assert!(
+                existing == name,
+                "route {} names parameter :{} but a sibling route calls it :{}",
+                url, name, existing
+            );
+            child.insert(method, url, segments, route);
+        } else if let Some(name) = segment.strip_prefix('*') {
+            assert!(!name.is_empty(), "route {} has an unnamed catch-all", url);
+            assert!(segments.next().is_none(), "route {} has segments after its catch-all", url);
+            let (existing, handlers) = self
+                .catch_all
+                .get_or_insert_with(|| (name.to_string(), Handlers::new()));
+            assert!(
+                existing == name,
+                "route {} names catch-all *{} but a sibling route calls it *{}",
+                url, name, existing
+            );
+            handlers.insert(method, route);
+        } else {
+            self.statics
+                .entry(segment.to_string())
+                .or_default()
+                .insert(method, url, segments, route);
+        }
+    }
+
+    // the handlers registered under exactly this pattern
+    fn get_mut(&mut self, mut segments: std::str::Split<'_, char>) -> Option<&mut Handlers> {
+        let segment = match segments.next() {
+            Some(segment) => segment,
+            None => return Some(&mut self.handlers),
+        };
+        if let Some(name) = segment.strip_prefix(':') {
+            let (existing, child) = self.param.as_mut()?;
+            if existing == name { child.get_mut(segments) } else { None }
+        } else if let Some(name) = segment.strip_prefix('*') {
+            let (existing, handlers) = self.catch_all.as_mut()?;
+            (existing == name && segments.next().is_none()).then_some(handlers)
+        } else {
+            self.statics.get_mut(segment)?.get_mut(segments)
+        }
+    }
+
+    // `path` is what is left of the request path after this node's segment,
+    // or None once every segment has been matched
+    fn find<'buf>(&self, path: Option<&'buf str>, params: &mut Params<'buf>) -> Option<&Handlers> {
+        let path = match path {
+            Some(path) => path,
+            None => return Some(&self.handlers).filter(|h| !h.is_empty()),
+        };
+        let (segment, rest) = match path.split_once('/') {
+            Some((segment, rest)) => (segment, Some(rest)),
+            None => (path, None),
+        };
+
+        // segments are decoded one at a time so an encoded / stays inside its segment
+        let decoded = urlencoded::percent_decode(segment);
+        if let Some(handlers) = self.statics.get(decoded.as_ref()).and_then(|child| child.find(rest, params)) {
+            return Some(handlers);
+        }
+        if let Some((name, child)) = &self.param {
+            if !segment.is_empty() {
+                params.push(name, decoded);
+                if let Some(handlers) = child.find(rest, params) {
+                    return Some(handlers);
+                }
+                params.pop();
+            }
+        }
+        if let Some((name, handlers)) = &self.catch_all {
+            params.push(name, urlencoded::percent_decode(path));
+            return Some(handlers);
+        }
+        None
+    }
+}
+
+#[derive(Default)]
 pub struct Router {
-    routes: HashMap<String, HandlerFunc>,
+    root: Node,
+    middleware: Vec<Box<dyn Middleware>>,
 }
 
 impl Router {
     pub fn new() -> Router {
-        Router { routes: HashMap::new() }
+        Router { root: Node::default(), middleware: Vec::new() }
+    }
+
+    /// Runs `middleware` around every request, including those that end in
+    /// 404 or 405. Middleware added first sees the request first.
+    pub fn wrap<M>(&mut self, middleware: M)
+    where M: Middleware + 'static
+    {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Registers a handler for `method` requests to `url`, which may contain
+    /// `:name` segments that match any single segment and end in a `*name`
+    /// segment that matches the rest of the path. Captured values are in
+    /// `Request::params`.
+    ///
+    /// HEAD and OPTIONS are answered automatically unless registered, and
+    /// paths that only have handlers for other methods get 405.
+    ///
+    /// Panics if the pattern is malformed or names a parameter differently
+    /// from an already registered route at the same position.
+    pub fn register<H>(&mut self, method: Method, url: &str, func: H)
+    where H: Fn(super::Request) -> super::Response + Send + Sync + 'static
+    {
+        self.root.insert(method, url, segments(url), Route { func: Box::new(func), max_body_size: None });
+    }
+
+    /// Lets `method` requests to `url`, an already registered route, carry
+    /// bodies of up to `max_body_size` instead of `Server::max_body_size`.
+    /// Meant for uploads, which are read into memory before the handler
+    /// runs:
+    ///
+    /// ```ignore
+    /// router.post("/upload", upload);
+    /// router.max_body_size(Method::POST, "/upload", 32 * 1024 * 1024);
+    /// ```
+    ///
+    /// Panics if no such route is registered.
+    pub fn max_body_size(&mut self, method: Method, url: &str, max_body_size: u64) {
+        let route = self.root.get_mut(segments(url)).and_then(|handlers| handlers.get_mut(&method));
+        match route {
+            Some(route) => route.max_body_size = Some(max_body_size),
+            None => panic!("no route {} {} to set a body size for", method.as_str(), url),
+        }
+    }
+
+    // the body size limit of the route `req` goes to, if it sets one
+    pub(crate) fn body_limit(&self, req: &Request) -> Option<u64> {
+        let mut params = Params::default();
+        let handlers = self.find(req.raw_path, &mut params)?;
+        route_for(handlers, req.method)?.max_body_size
+    }
+
+    pub fn get<H>(&mut self, url: &str, func: H)
+    where H: Fn(super::Request) -> super::Response + Send + Sync + 'static
+    {
+        self.register(Method::GET, url, func);
+    }
+
+    pub fn post<H>(&mut self, url: &str, func: H)
+    where H: Fn(super::Request) -> super::Response + Send + Sync + 'static
+    {
+        self.register(Method::POST, url, func);
     }
 
-    pub fn register<H>(&mut self, url: &str, func: H) 
-    where H: Fn(super::Request) -> super::Response + 'static
+    pub fn put<H>(&mut self, url: &str, func: H)
+    where H: Fn(super::Request) -> super::Response + Send + Sync + 'static
     {
-        self.routes.insert(url.to_string(), Box::new(func));
+        self.register(Method::PUT, url, func);
+    }
+
+    pub fn patch<H>(&mut self, url: &str, func: H)
+    where H: Fn(super::Request) -> super::Response + Send + Sync + 'static
+    {
+        self.register(Method::PATCH, url, func);
+    }
+
+    pub fn delete<H>(&mut self, url: &str, func: H)
+    where H: Fn(super::Request) -> super::Response + Send + Sync + 'static
+    {
+        self.register(Method::DELETE, url, func);
+    }
+
+    /// Serves the files under a directory at `prefix`, e.g. mounting at
+    /// `/static` answers `GET /static/css/site.css`.
+    pub fn mount(&mut self, prefix: &str, files: StaticFiles) {
+        let prefix = prefix.trim_end_matches('/');
+        let files = Arc::new(files);
+
+        let root = Arc::clone(&files);
+        self.get(if prefix.is_empty() { "/" } else { prefix }, move |req| root.serve(&req, ""));
+        self.get(&format!("{}/*path", prefix), move |req| {
+            let path = req.params.get("path").unwrap_or("");
+            files.serve(&req, path)
+        });
     }
 
     pub fn handle_request(&self, req: Request) -> Response {
-        match self.routes.get(req.path) {
-            Some(func) => func(req),
-            None => Response::new(StatusCode::NotFound, None)
+        Next::new(&self.middleware, &|req| self.dispatch(req)).run(req)
+    }
+
+    fn find<'buf>(&self, raw_path: &'buf str, params: &mut Params<'buf>) -> Option<&Handlers> {
+        match raw_path.strip_prefix('/') {
+            Some("") => self.root.find(None, params),
+            Some(path) => self.root.find(Some(path), params),
+            None => None,
         }
     }
-}
\ No newline at end of file
+
+    fn dispatch(&self, mut req: Request) -> Response {
+        let handlers = match self.find(req.raw_path, &mut req.params) {
+            Some(handlers) => handlers,
+            None => return Response::new(StatusCode::NotFound, None),
+        };
+
+        let method = req.method;
+        match (route_for(handlers, method), method) {
+            (Some(route), Method::HEAD) => {
+                let mut resp = (route.func)(req);
+                resp.strip_body();
+                resp
+            }
+            (Some(route), _) => (route.func)(req),
+            (None, Method::OPTIONS) => {
+                let mut resp = Response::new(StatusCode::NoContent, None);
+                resp.set_header("Allow", &allow(handlers));
+                resp
+            }
+            (None, _) => {
+                let mut resp = Response::new(StatusCode::MethodNotAllowed, None);
+                resp.set_header("Allow", &allow(handlers));
+                resp
+            }
+        }
+    }
+}
+
+// HEAD falls back to the GET handler
+fn route_for(handlers: &Handlers, method: Method) -> Option<&Route> {
+    handlers.get(&method).or_else(|| match method {
+        Method::HEAD => handlers.get(&Method::GET),
+        _ => None,
+    })
+}
+
+// every method a path answers to, including the implicit HEAD and OPTIONS
+fn allow(handlers: &Handlers) -> String {
+    let mut methods: Vec<Method> = handlers.keys().copied().collect();
+    if handlers.contains_key(&Method::GET) {
+        methods.push(Method::HEAD);
+    }
+    methods.push(Method::OPTIONS);
+    methods.sort();
+    methods.dedup();
+    methods.iter().map(Method::as_str).collect::<Vec<_>>().join(", ")
+}
+
+// "/users/:id" -> ["users", ":id"], "/" -> []
+fn segments(url: &str) -> std::str::Split<'_, char> {
+    let url = url.strip_prefix('/').unwrap_or(url);
+    let mut segments = url.split('/');
+    if url.is_empty() {
+        segments.next();
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(_: Request) -> Response {
+        Response::new(StatusCode::Ok, None)
+    }
+
+    fn limit(router: &Router, head: &str) -> Option<u64> {
+        let req = Request::try_from(head.as_bytes()).unwrap();
+        router.body_limit(&req)
+    }
+
+    #[test]
+    fn routes_can_raise_the_body_limit() {
+        let mut router = Router::new();
+        router.post("/upload", ok);
+        router.put("/files/:id", ok);
+        router.get("/static/*path", ok);
+        router.post("/other", ok);
+        router.max_body_size(Method::POST, "/upload", 100);
+        router.max_body_size(Method::PUT, "/files/:id", 200);
+        router.max_body_size(Method::GET, "/static/*path", 300);
+
+        assert_eq!(limit(&router, "POST /upload HTTP/1.1\r\n\r\n"), Some(100));
+        assert_eq!(limit(&router, "PUT /files/7 HTTP/1.1\r\n\r\n"), Some(200));
+        assert_eq!(limit(&router, "HEAD /static/a/b HTTP/1.1\r\n\r\n"), Some(300));
+        assert_eq!(limit(&router, "POST /other HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(limit(&router, "PUT /upload HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(limit(&router, "POST /missing HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no route PUT /upload")]
+    fn a_body_limit_needs_a_registered_route() {
+        let mut router = Router::new();
+        router.post("/upload", ok);
+        router.max_body_size(Method::PUT, "/upload", 100);
+    }
+}
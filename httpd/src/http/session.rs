@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{Middleware, Next, Request, Response, SameSite, SetCookie};
+
+const DEFAULT_COOKIE_NAME: &str = "httpd_session";
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const MIN_SECRET_LEN: usize = 32;
+const ID_LEN: usize = 32;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub type SessionData = HashMap<String, String>;
+
+/// Where session data lives between requests.
+pub trait SessionStore: Send + Sync {
+    /// Returns the data saved under `id`, unless it has expired.
+    fn load(&self, id: &str) -> Option<SessionData>;
+    /// Saves `data` under `id`, to expire `ttl` from now.
+    fn save(&self, id: &str, data: SessionData, ttl: Duration);
+    fn destroy(&self, id: &str);
+}
+
+/// Keeps sessions in memory, so they are lost when the server restarts.
+#[derive(Debug)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Debug)]
+struct MemoryState {
+    entries: HashMap<String, (SessionData, Instant)>,
+    last_sweep: Instant,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MemoryState { entries: HashMap::new(), last_sweep: Instant::now() }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        let mut state = self.state();
+        match state.entries.get(id) {
+            Some((data, expires)) if *expires > Instant::now() => Some(data.clone()),
+            Some(_) => {
+                state.entries.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn save(&self, id: &str, data: SessionData, ttl: Duration) {
+        let mut state = self.state();
+        let now = Instant::now();
+        // abandoned sessions are never loaded again, so they are swept here
+        if now.duration_since(state.last_sweep) >= SWEEP_INTERVAL {
+            state.entries.retain(|_, (_, expires)| *expires > now);
+            state.last_sweep = now;
+        }
+        state.entries.insert(id.to_string(), (data, now + ttl));
+    }
+
+    fn destroy(&self, id: &str) {
+        self.state().entries.remove(id);
+    }
+}
+
+/// The current request's session, handed out by `Request::session`.
+///
+/// Values are stored as strings and converted on the way in and out, so any
+/// type implementing `ToString` and `FromStr` can be kept in a session.
+#[derive(Debug, Clone)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    data: SessionData,
+    changed: bool,
+    destroyed: bool,
+    regenerate: bool,
+}
+
+impl Session {
+    fn new(data: SessionData) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SessionState { data, ..Default::default() })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the value under `key`, or None if it is missing or doesn't
+    /// parse as a `T`.
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.state().data.get(key).and_then(|v| v.parse().ok())
+    }
+
+    pub fn set<T: ToString>(&self, key: &str, value: T) {
+        let mut state = self.state();
+        state.data.insert(key.to_string(), value.to_string());
+        state.changed = true;
+    }
+
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state();
+        state.changed |= state.data.remove(key).is_some();
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.state().data.contains_key(key)
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.data.clear();
+        state.changed = true;
+    }
+
+    /// Deletes the session from the store and tells the browser to forget
+    /// its cookie, e.g. on logout.
+    pub fn destroy(&self) {
+        let mut state = self.state();
+        state.data.clear();
+        state.destroyed = true;
+    }
+
+    /// Moves the data to a new session id. Call this when the user's
+    /// privileges change, e.g. on login, to prevent session fixation.
+    pub fn regenerate(&self) {
+        self.state().regenerate = true;
+    }
+}
+
+impl Request<'_> {
+    /// The session attached by `SessionLayer`.
+    ///
+    /// Panics if the router has no `SessionLayer`.
+    pub fn session(&self) -> &Session {
+        self.extensions
+            .get::<Session>()
+            .expect("Request::session needs a SessionLayer on the router")
+    }
+}
+
+/// Middleware giving every request a `Session`.
+///
+/// The session id travels in a cookie signed with HMAC-SHA256, so ids that
+/// weren't issued by this server are ignored without touching the store.
+/// A session is only saved, and its cookie only sent, once something has
+/// been stored in it.
+///
+/// ```ignore
+/// router.wrap(SessionLayer::new(&secret).secure(true));
+/// router.get("/", |req| {
+///     let visits = req.session().get::<u32>("visits").unwrap_or(0) + 1;
+///     req.session().set("visits", visits);
+///     Response::with_body(StatusCode::Ok, format!("visit {}", visits))
+/// });
+/// ```
+pub struct SessionLayer {
+    store: Box<dyn SessionStore>,
+    key: Vec<u8>,
+    cookie_name: String,
+    ttl: Duration,
+    secure: bool,
+    same_site: SameSite,
+}
+
+impl SessionLayer {
+    /// Uses a `MemoryStore`. Panics if `secret` is shorter than 32 bytes.
+    pub fn new(secret: &[u8]) -> Self {
+        assert!(
+            secret.len() >= MIN_SECRET_LEN,
+            "session secret must be at least {} bytes",
+            MIN_SECRET_LEN
+        );
+        Self {
+            store: Box::new(MemoryStore::new()),
+            key: secret.to_vec(),
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+            ttl: DEFAULT_TTL,
+            secure: false,
+            same_site: SameSite::Lax,
+        }
+    }
+
+    pub fn store(mut self, store: impl SessionStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie_name = name.to_string();
+        self
+    }
+
+    /// How long a session lives without being used.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::new_from_slice(&self.key).expect("HMAC takes keys of any size")
+    }
+
+    fn sign(&self, id: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(id.as_bytes());
+        format!("{}.{}", id, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    // returns the id if the cookie carries a valid signature
+    fn verify<'a>(&self, value: &'a str) -> Option<&'a str> {
+        let (id, signature) = value.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(id.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        Some(id)
+    }
+
+    fn cookie(&self, value: &str) -> SetCookie {
+        SetCookie::new(&self.cookie_name, value)
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+    }
+}
+
+impl Middleware for SessionLayer {
+    fn handle(&self, mut req: Request, next: Next) -> Response {
+        let cookies = req.cookies();
+        let id = cookies.get(&self.cookie_name).and_then(|v| self.verify(v));
+        // an unknown id is never adopted, the session starts over with a new one
+        let (mut id, data) = match id.and_then(|id| Some((id, self.store.load(id)?))) {
+            Some((id, data)) => (Some(id.to_string()), data),
+            None => (None, SessionData::new()),
+        };
+
+        let session = Session::new(data);
+        req.extensions.insert(session.clone());
+        let mut resp = next.run(req);
+
+        let mut state = session.state();
+        if state.destroyed || (state.changed && state.data.is_empty()) {
+            if let Some(id) = id {
+                self.store.destroy(&id);
+                resp.set_cookie(&self.cookie("").max_age(Duration::ZERO));
+            }
+            return resp;
+        }
+        if state.regenerate {
+            if let Some(id) = id.take() {
+                self.store.destroy(&id);
+            }
+        }
+        if state.data.is_empty() {
+            return resp;
+        }
+
+        let id = id.unwrap_or_else(new_id);
+        // saving on every request keeps active sessions from expiring
+        self.store.save(&id, std::mem::take(&mut state.data), self.ttl);
+        resp.set_cookie(&self.cookie(&self.sign(&id)).max_age(self.ttl));
+        resp
+    }
+}
+
+fn new_id() -> String {
+    let mut bytes = [0; ID_LEN];
+    getrandom::getrandom(&mut bytes).expect("no system randomness available");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
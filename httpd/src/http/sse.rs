@@ -0,0 +1,187 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::{Response, ResponseBody, StatusCode};
+
+const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(15);
+// events waiting to be written before `EventSender::send` blocks
+const CHANNEL_SIZE: usize = 16;
+
+/// One server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// An unnamed event, which browsers deliver to `onmessage`. The data may
+    /// span several lines.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self { data: data.into(), ..Default::default() }
+    }
+
+    /// Sent back by a reconnecting browser in `Last-Event-ID`.
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// The event type, for listeners added with `addEventListener`.
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(event.to_string());
+        self
+    }
+
+    /// How long the browser waits before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        // a newline would end the field and let the rest pose as another one
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", id.replace(['\r', '\n', '\0'], ""))?;
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", event.replace(['\r', '\n'], ""))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        for line in self.data.replace("\r\n", "\n").split(['\r', '\n']) {
+            writeln!(f, "data: {}", line)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Creates an event stream and the sender that feeds it.
+///
+/// The handler returns the stream as its response and keeps pushing events
+/// from another thread. Each open stream occupies a worker thread until it
+/// ends, the same workers that answer every other request, so size
+/// `Server::workers` for the expected number of clients on top of the usual
+/// load. Once all workers are taken, further requests, new streams
+/// included, are answered with 503 Service Unavailable.
+///
+/// ```ignore
+/// router.get("/events", |_| {
+///     let (sender, stream) = sse::channel();
+///     thread::spawn(move || {
+///         for i in 0.. {
+///             if sender.send(Event::new(format!("tick {}", i)).id(&i.to_string())).is_err() {
+///                 break; // the client went away
+///             }
+///             thread::sleep(Duration::from_secs(1));
+///         }
+///     });
+///     stream.into_response()
+/// });
+/// ```
+pub fn channel() -> (EventSender, EventStream) {
+    let (sender, receiver) = mpsc::sync_channel(CHANNEL_SIZE);
+    let connected = Arc::new(AtomicBool::new(true));
+    let stream = EventStream {
+        receiver,
+        heartbeat: DEFAULT_HEARTBEAT,
+        pending: Vec::new(),
+        pos: 0,
+        connected: Arc::clone(&connected),
+    };
+    (EventSender { sender, connected }, stream)
+}
+
+/// Pushes events to a client. Dropping every sender ends the stream.
+#[derive(Debug, Clone)]
+pub struct EventSender {
+    sender: SyncSender<Event>,
+    connected: Arc<AtomicBool>,
+}
+
+/// The client has disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+impl EventSender {
+    /// Queues `event`, waiting if the client is falling behind. Fails once
+    /// the client has disconnected.
+    pub fn send(&self, event: Event) -> Result<(), Disconnected> {
+        if !self.is_connected() {
+            return Err(Disconnected);
+        }
+        self.sender.send(event).map_err(|_| Disconnected)
+    }
+
+    /// Disconnects are noticed when a write fails, at the latest with the
+    /// next heartbeat.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+}
+
+/// The body of a `text/event-stream` response, see `channel`.
+///
+/// While no events come in a comment line is sent every heartbeat interval,
+/// which keeps proxies from timing the connection out and reveals clients
+/// that have gone away.
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: Receiver<Event>,
+    heartbeat: Duration,
+    pending: Vec<u8>,
+    pos: usize,
+    connected: Arc<AtomicBool>,
+}
+
+impl EventStream {
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = interval;
+        self
+    }
+
+    pub fn into_response(self) -> Response {
+        Response::builder(StatusCode::Ok)
+            .content_type("text/event-stream")
+            .cache_control("no-cache")
+            .body(ResponseBody::stream(self, None))
+            .build()
+    }
+}
+
+impl Read for EventStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.pending.len() {
+            self.pending = match self.receiver.recv_timeout(self.heartbeat) {
+                Ok(event) => event.to_string().into_bytes(),
+                Err(RecvTimeoutError::Timeout) => b":\n\n".to_vec(),
+                // every sender is gone, the stream is over
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+            self.pos = 0;
+        }
+        let n = (&self.pending[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Drop for EventStream {
+    // the response is dropped once the client is gone or the stream ended
+    fn drop(&mut self) {
+        self.connected.store(false, Ordering::Relaxed);
+    }
+}
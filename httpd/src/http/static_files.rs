@@ -0,0 +1,305 @@
+use std::{
+    fmt::Write,
+    fs::{self, File, Metadata},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use super::{
+    encoding::{self, add_vary, ContentEncoding},
+    mime, range, Request, Response, StatusCode,
+};
+
+// suffixes of precompressed copies, in order of preference
+const PRECOMPRESSED: [(ContentEncoding, &str); 2] = [
+    (ContentEncoding::Brotli, "br"),
+    (ContentEncoding::Gzip, "gz"),
+];
+
+/// Serves files from a directory. Mount it with `Router::mount`.
+///
+/// Request paths can't reach outside the directory: `..` segments are
+/// refused and symlinks that lead elsewhere are treated as missing.
+///
+/// Files are sent with an `ETag` and `Last-Modified`, and clients that
+/// already have the current version get 304 Not Modified. Range requests
+/// are answered with 206 Partial Content.
+///
+/// With `precompressed` set, a `.br` or `.gz` copy next to a file is sent
+/// instead to clients that accept that coding.
+#[derive(Debug)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index: Option<String>,
+    listing: bool,
+    cache_control: Option<String>,
+    precompressed: bool,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            index: Some(String::from("index.html")),
+            listing: false,
+            cache_control: None,
+            precompressed: false,
+        }
+    }
+
+    /// File served for directory requests, `index.html` unless changed.
+    pub fn index(mut self, index: Option<&str>) -> Self {
+        self.index = index.map(String::from);
+        self
+    }
+
+    /// Render an HTML listing for directories without an index file.
+    pub fn directory_listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    /// `Cache-Control` sent with every file, e.g. `public, max-age=3600`.
+    pub fn cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = Some(cache_control.to_string());
+        self
+    }
+
+    /// Look for `style.css.br` and `style.css.gz` when serving `style.css`.
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Serves `path`, which is relative to the mounted directory.
+    pub fn serve(&self, req: &Request, path: &str) -> Response {
+        let file_path = match self.resolve(path) {
+            Some(file_path) => file_path,
+            None => return Response::new(StatusCode::NotFound, None),
+        };
+        let metadata = match fs::metadata(&file_path) {
+            Ok(metadata) => metadata,
+            Err(e) => return error_response(e),
+        };
+
+        if !metadata.is_dir() {
+            return self.serve_file(req, &file_path, &metadata);
+        }
+
+        // relative links in the index only work from a path ending in /
+        if !req.raw_path.ends_with('/') {
+            return Response::builder(StatusCode::MovedPermanently)
+                .header("Location", &directory_location(req))
+                .build();
+        }
+        if let Some(index) = &self.index {
+            let index_path = file_path.join(index);
+            if let Ok(metadata) = fs::metadata(&index_path) {
+                if metadata.is_file() {
+                    return self.serve_file(req, &index_path, &metadata);
+                }
+            }
+        }
+        if self.listing {
+            let at_root = file_path == self.root;
+            return match render_listing(&file_path, &req.path, at_root) {
+                Ok(html) => Response::builder(StatusCode::Ok)
+                    .content_type("text/html; charset=utf-8")
+                    .body(html)
+                    .build(),
+                Err(e) => error_response(e),
+            };
+        }
+        Response::new(StatusCode::NotFound, None)
+    }
+
+    fn serve_file(&self, req: &Request, path: &Path, metadata: &Metadata) -> Response {
+        let siblings = if self.precompressed { self.precompressed_siblings(path) } else { Vec::new() };
+        let available = siblings.iter().map(|(encoding, ..)| *encoding).collect::<Vec<_>>();
+        let chosen = encoding::negotiate(req, &available)
+            .and_then(|chosen| siblings.into_iter().find(|(encoding, ..)| *encoding == chosen));
+        // the type is that of the original, not of the compressed copy
+        let content_type = mime::from_path(path);
+        let (path, metadata) = match &chosen {
+            Some((_, sibling, sibling_metadata)) => (sibling.as_path(), sibling_metadata),
+            None => (path, metadata),
+        };
+
+        let mut resp = Response::builder(StatusCode::Ok)
+            .content_type(content_type)
+            .etag(&etag(metadata));
+        if let Ok(modified) = metadata.modified() {
+            resp = resp.last_modified(modified);
+        }
+        if let Some(cache_control) = &self.cache_control {
+            resp = resp.cache_control(cache_control);
+        }
+        if let Some((encoding, ..)) = &chosen {
+            resp = resp.header("Content-Encoding", encoding.as_str());
+        }
+        let mut resp = resp.build();
+        if !available.is_empty() {
+            add_vary(&mut resp, "Accept-Encoding");
+        }
+
+        if resp.is_fresh_for(req) {
+            return resp.into_not_modified();
+        }
+        match File::open(path) {
+            Ok(file) => range::serve_ranges(req, resp, file, metadata.len()),
+            Err(e) => error_response(e),
+        }
+    }
+
+    // maps a request path onto the file system, None if it would escape root
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut resolved = self.root.clone();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return None,
+                _ if segment.contains(['\\', '\0']) => return None,
+                _ => resolved.push(segment),
+            }
+        }
+
+        // symlinks may still point elsewhere
+        let root = self.root.canonicalize().ok()?;
+        match resolved.canonicalize() {
+            Ok(canonical) if canonical.starts_with(&root) => Some(resolved),
+            Ok(_) => None,
+            // missing files are reported by the caller
+            Err(_) => Some(resolved),
+        }
+    }
+
+    // the compressed copies of `path` that exist, held to the same root as
+    // `resolve` holds the original to
+    fn precompressed_siblings(&self, path: &Path) -> Vec<(ContentEncoding, PathBuf, Metadata)> {
+        let Ok(root) = self.root.canonicalize() else {
+            return Vec::new();
+        };
+        PRECOMPRESSED
+            .iter()
+            .filter_map(|(encoding, extension)| {
+                let mut sibling = path.as_os_str().to_owned();
+                sibling.push(".");
+                sibling.push(extension);
+                let sibling = PathBuf::from(sibling);
+                if !sibling.canonicalize().ok()?.starts_with(&root) {
+                    return None;
+                }
+                let metadata = fs::metadata(&sibling).ok().filter(|m| m.is_file())?;
+                Some((*encoding, sibling, metadata))
+            })
+            .collect()
+    }
+}
+
+// changes whenever the file is modified or resized
+fn etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    format!("\"{:x}-{:x}\"", modified, metadata.len())
+}
+
+// the directory's path with a / added, for the redirect to it. Leading
+// slashes are collapsed, as "//host/dir" would send the client to another
+// host, and browsers read a backslash there as a slash.
+fn directory_location(req: &Request) -> String {
+    let mut location = format!("/{}/", req.raw_path.trim_start_matches(['/', '\\']));
+    if let Some(query) = &req.query_str {
+        location.push('?');
+        location.push_str(query.as_str());
+    }
+    location
+}
+
+fn error_response(e: io::Error) -> Response {
+    match e.kind() {
+        ErrorKind::NotFound => Response::new(StatusCode::NotFound, None),
+        ErrorKind::PermissionDenied => Response::new(StatusCode::Forbidden, None),
+        _ => Response::new(StatusCode::InternalServerError, None),
+    }
+}
+
+fn render_listing(dir: &Path, url_path: &str, at_root: bool) -> io::Result<String> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                name.push('/');
+            }
+            name
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let title = escape_html(url_path);
+    let mut html = String::new();
+    write!(html, "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n", title).unwrap();
+    if !at_root {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for name in &entries {
+        writeln!(html, "<li><a href=\"{}\">{}</a></li>", encode_href(name), escape_html(name)).unwrap();
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// percent-encodes a file name for use as a relative link
+fn encode_href(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(b as char)
+            }
+            _ => write!(encoded, "%{:02X}", b).unwrap(),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(head: &str) -> String {
+        directory_location(&Request::try_from(head.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn redirects_directories_to_a_path_on_this_host() {
+        assert_eq!(location("GET /docs HTTP/1.1\r\n\r\n"), "/docs/");
+        assert_eq!(location("GET //evil.example/docs HTTP/1.1\r\n\r\n"), "/evil.example/docs/");
+        assert_eq!(location("GET /\\evil.example/docs HTTP/1.1\r\n\r\n"), "/evil.example/docs/");
+    }
+
+    #[test]
+    fn redirects_keep_the_query_string() {
+        assert_eq!(location("GET /docs?sort=name&q=a%20b HTTP/1.1\r\n\r\n"), "/docs/?sort=name&q=a%20b");
+        assert_eq!(location("GET /docs? HTTP/1.1\r\n\r\n"), "/docs/?");
+    }
+}
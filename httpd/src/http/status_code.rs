@@ -1,26 +1,231 @@
-use std::fmt::Display;
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    hash::{Hash, Hasher},
+};
 
-#[derive(Debug, Copy, Clone)]
-pub enum StatusCode{
-    Ok = 200,
-    BadRequest = 400,
-    NotFound = 404,
-    MethodNotAllowed = 405,
+macro_rules! status_codes {
+    ($($code:literal $name:ident $phrase:literal,)+) => {
+        /// An HTTP status code. Every IANA-registered code has a variant;
+        /// anything else in 100..=599 is `Custom`, made with `from_u16`.
+        #[derive(Debug, Copy, Clone)]
+        pub enum StatusCode {
+            $($name,)+
+            Custom(CustomCode),
+        }
+
+        impl StatusCode {
+            pub fn as_u16(&self) -> u16 {
+                match self {
+                    $(Self::$name => $code,)+
+                    Self::Custom(code) => code.0,
+                }
+            }
+
+            /// The registered reason phrase, empty for custom codes.
+            pub fn reason_phrase(&self) -> &str {
+                match self {
+                    $(Self::$name => $phrase,)+
+                    Self::Custom(_) => "",
+                }
+            }
+
+            /// Maps a code to its variant, falling back to `Custom` for
+            /// unregistered codes. Returns None outside 100..=599, the
+            /// classes HTTP defines.
+            pub fn from_u16(code: u16) -> Option<Self> {
+                match code {
+                    $($code => Some(Self::$name),)+
+                    100..=599 => Some(Self::Custom(CustomCode(code))),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+/// An unregistered status code, always in 100..=599.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CustomCode(u16);
+
+impl CustomCode {
+    pub fn get(&self) -> u16 {
+        self.0
+    }
+}
+
+status_codes! {
+    100 Continue "Continue",
+    101 SwitchingProtocols "Switching Protocols",
+    102 Processing "Processing",
+    103 EarlyHints "Early Hints",
+
+    200 Ok "OK",
+    201 Created "Created",
+    202 Accepted "Accepted",
+    203 NonAuthoritativeInformation "Non-Authoritative Information",
+    204 NoContent "No Content",
+    205 ResetContent "Reset Content",
+    206 PartialContent "Partial Content",
+    207 MultiStatus "Multi-Status",
+    208 AlreadyReported "Already Reported",
+    226 ImUsed "IM Used",
+
+    300 MultipleChoices "Multiple Choices",
+    301 MovedPermanently "Moved Permanently",
+    302 Found "Found",
+    303 SeeOther "See Other",
+    304 NotModified "Not Modified",
+    305 UseProxy "Use Proxy",
+    307 TemporaryRedirect "Temporary Redirect",
+    308 PermanentRedirect "Permanent Redirect",
+
+    400 BadRequest "Bad Request",
+    401 Unauthorized "Unauthorized",
+    402 PaymentRequired "Payment Required",
+    403 Forbidden "Forbidden",
+    404 NotFound "Not Found",
+    405 MethodNotAllowed "Method Not Allowed",
+    406 NotAcceptable "Not Acceptable",
+    407 ProxyAuthenticationRequired "Proxy Authentication Required",
+    408 RequestTimeout "Request Timeout",
+    409 Conflict "Conflict",
+    410 Gone "Gone",
+    411 LengthRequired "Length Required",
+    412 PreconditionFailed "Precondition Failed",
+    413 PayloadTooLarge "Payload Too Large",
+    414 UriTooLong "URI Too Long",
+    415 UnsupportedMediaType "Unsupported Media Type",
+    416 RangeNotSatisfiable "Range Not Satisfiable",
+    417 ExpectationFailed "Expectation Failed",
+    421 MisdirectedRequest "Misdirected Request",
+    422 UnprocessableContent "Unprocessable Content",
+    423 Locked "Locked",
+    424 FailedDependency "Failed Dependency",
+    425 TooEarly "Too Early",
+    426 UpgradeRequired "Upgrade Required",
+    428 PreconditionRequired "Precondition Required",
+    429 TooManyRequests "Too Many Requests",
+    431 RequestHeaderFieldsTooLarge "Request Header Fields Too Large",
+    451 UnavailableForLegalReasons "Unavailable For Legal Reasons",
+
+    500 InternalServerError "Internal Server Error",
+    501 NotImplemented "Not Implemented",
+    502 BadGateway "Bad Gateway",
+    503 ServiceUnavailable "Service Unavailable",
+    504 GatewayTimeout "Gateway Timeout",
+    505 HttpVersionNotSupported "HTTP Version Not Supported",
+    506 VariantAlsoNegotiates "Variant Also Negotiates",
+    507 InsufficientStorage "Insufficient Storage",
+    508 LoopDetected "Loop Detected",
+    510 NotExtended "Not Extended",
+    511 NetworkAuthenticationRequired "Network Authentication Required",
 }
 
 impl StatusCode {
-    pub fn reason_phrase(&self) -> &str {
-        match self {
-            Self::Ok => "Ok",
-            Self::BadRequest => "Bad Request",
-            Self::NotFound => "Not Found",
-            StatusCode::MethodNotAllowed => "Method Not Allowed",
-        }
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.as_u16())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.as_u16())
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+
+    /// 1xx, 204 and 304 responses never carry a body (RFC 7230 3.3.3).
+    pub fn allows_body(&self) -> bool {
+        !(self.is_informational() || matches!(self, Self::NoContent | Self::NotModified))
+    }
+}
+
+// a status is its code
+impl PartialEq for StatusCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_u16() == other.as_u16()
+    }
+}
+
+impl Eq for StatusCode {}
+
+impl Hash for StatusCode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_u16().hash(state)
+    }
+}
+
+impl TryFrom<u16> for StatusCode {
+    type Error = InvalidStatusCode;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Self::from_u16(code).ok_or(InvalidStatusCode)
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status_code: StatusCode) -> u16 {
+        status_code.as_u16()
     }
 }
 
 impl Display for StatusCode {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-       write!(f, "{} {}", *self as u16, self.reason_phrase())
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+       write!(f, "{} {}", self.as_u16(), self.reason_phrase())
     }
-}
\ No newline at end of file
+}
+
+pub struct InvalidStatusCode;
+
+impl Error for InvalidStatusCode {}
+
+impl Display for InvalidStatusCode {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Invalid Status Code")
+    }
+}
+
+impl Debug for InvalidStatusCode {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Invalid Status Code")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_codes_map_to_their_variant() {
+        assert!(matches!(StatusCode::from_u16(404), Some(StatusCode::NotFound)));
+        assert_eq!(StatusCode::NotFound.to_string(), "404 Not Found");
+    }
+
+    #[test]
+    fn other_codes_in_range_are_custom() {
+        let code = StatusCode::from_u16(599).unwrap();
+        assert!(matches!(code, StatusCode::Custom(custom) if custom.get() == 599));
+        assert_eq!(code.as_u16(), 599);
+        assert_eq!(code.reason_phrase(), "");
+        assert!(code.is_server_error());
+        assert!(StatusCode::from_u16(199).unwrap().is_informational());
+    }
+
+    #[test]
+    fn codes_outside_the_http_classes_are_rejected() {
+        for code in [0, 99, 600, 999, 1000, u16::MAX] {
+            assert_eq!(StatusCode::from_u16(code), None, "{}", code);
+            assert!(StatusCode::try_from(code).is_err(), "{}", code);
+        }
+    }
+}
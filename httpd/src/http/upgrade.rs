@@ -0,0 +1,99 @@
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// Anything a connection can be read from and written to.
+pub trait Io: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Io for T {}
+
+/// A connection taken over after a `101 Switching Protocols` response.
+///
+/// Whatever the client sent after the request and the server had already
+/// buffered is read first.
+pub struct Upgraded {
+    buffered: Vec<u8>,
+    pos: usize,
+    io: Box<dyn Io>,
+    socket: Option<TcpStream>,
+}
+
+impl Upgraded {
+    pub(crate) fn new(io: Box<dyn Io>, buffered: Vec<u8>, socket: Option<TcpStream>) -> Self {
+        Self { buffered, pos: 0, io, socket }
+    }
+
+    /// Upgraded connections start without read or write timeouts.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.set_read_timeout(timeout),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.set_write_timeout(timeout),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Read for Upgraded {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.buffered.len() {
+            let n = (&self.buffered[self.pos..]).read(buf)?;
+            self.pos += n;
+            return Ok(n);
+        }
+        self.io.read(buf)
+    }
+}
+
+impl Write for Upgraded {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl Debug for Upgraded {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("Upgraded").field("socket", &self.socket).finish()
+    }
+}
+
+/// What to do with the connection once the response has switched protocols,
+/// see `Response::on_upgrade`.
+pub struct OnUpgrade(Box<dyn FnOnce(Upgraded) + Send>);
+
+impl OnUpgrade {
+    pub(crate) fn call(self, upgraded: Upgraded) {
+        (self.0)(upgraded)
+    }
+}
+
+impl Debug for OnUpgrade {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "OnUpgrade")
+    }
+}
+
+impl super::Response {
+    /// Hands the connection to `f` on a thread of its own once this response
+    /// is sent. Only used when the status is 101 Switching Protocols.
+    pub fn on_upgrade(&mut self, f: impl FnOnce(Upgraded) + Send + 'static) {
+        self.upgrade = Some(OnUpgrade(Box::new(f)));
+    }
+
+    pub(crate) fn take_upgrade(&mut self) -> Option<OnUpgrade> {
+        let upgrade = self.upgrade.take();
+        upgrade.filter(|_| self.status_code() == super::StatusCode::SwitchingProtocols)
+    }
+}
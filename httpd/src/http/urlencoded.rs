@@ -0,0 +1,52 @@
+use std::borrow::Cow;
+
+/// Decodes `%XX` escapes. Malformed escapes are kept as they are and bytes
+/// that don't form valid UTF-8 become U+FFFD. Borrows when there is nothing
+/// to decode.
+pub fn percent_decode(s: &str) -> Cow<'_, str> {
+    if !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1).and_then(hex), bytes.get(i + 2).and_then(hex)) {
+            (b'%', Some(hi), Some(lo)) => {
+                decoded.push(hi << 4 | lo);
+                i += 3;
+            }
+            (b, _, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    match String::from_utf8(decoded) {
+        Ok(s) => Cow::Owned(s),
+        Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+    }
+}
+
+/// Decodes one key or value of an `application/x-www-form-urlencoded`
+/// string, where `+` also stands for a space.
+pub fn decode_component(s: &str) -> Cow<'_, str> {
+    if !s.contains('+') {
+        return percent_decode(s);
+    }
+    Cow::Owned(percent_decode(&s.replace('+', " ")).into_owned())
+}
+
+/// Splits `a=1&b=two+words&flag` into decoded key/value pairs. Keys without
+/// `=` get an empty value.
+pub fn parse(s: &str) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+    s.split('&').filter(|kv| !kv.is_empty()).map(|kv| {
+        let (key, value) = kv.split_once('=').unwrap_or((kv, ""));
+        (decode_component(key), decode_component(value))
+    })
+}
+
+fn hex(b: &u8) -> Option<u8> {
+    (*b as char).to_digit(16).map(|d| d as u8)
+}
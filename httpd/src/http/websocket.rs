@@ -0,0 +1,654 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    io::{self, BufReader, ErrorKind, Read, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha1::{Digest, Sha1};
+
+use super::{Method, Request, Response, Router, StatusCode, Upgraded};
+
+// appended to the client's key to prove the server speaks WebSocket (RFC 6455 1.3)
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+const MAX_CONTROL_PAYLOAD: u64 = 125;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Answers a WebSocket handshake, then runs `f` with the connection on a
+/// thread of its own. Requests that aren't a valid handshake get 426 or 400.
+///
+/// ```ignore
+/// router.get("/chat/:room", |req| {
+///     let room = req.params.get("room").unwrap_or("lobby").to_string();
+///     websocket::upgrade(&req, move |ws| chat(room, ws))
+/// });
+/// ```
+pub fn upgrade<F>(req: &Request, f: F) -> Response
+where
+    F: FnOnce(WebSocket) + Send + 'static,
+{
+    if req.method != Method::GET || !has_token(req, "Upgrade", "websocket") || !has_token(req, "Connection", "upgrade") {
+        return Response::builder(StatusCode::UpgradeRequired)
+            .header("Upgrade", "websocket")
+            .header("Connection", "Upgrade")
+            .build();
+    }
+    if req.headers.get("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Response::builder(StatusCode::UpgradeRequired)
+            .header("Sec-WebSocket-Version", "13")
+            .build();
+    }
+    let key = match req.headers.get("Sec-WebSocket-Key").map(str::trim) {
+        Some(key) if STANDARD.decode(key).is_ok_and(|k| k.len() == 16) => key,
+        _ => return Response::new(StatusCode::BadRequest, None),
+    };
+
+    let mut resp = Response::builder(StatusCode::SwitchingProtocols)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", &accept_key(key))
+        .build();
+    resp.on_upgrade(move |upgraded| f(WebSocket::new(upgraded)));
+    resp
+}
+
+/// The `Sec-WebSocket-Accept` value for a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(GUID.as_bytes());
+    STANDARD.encode(sha1.finalize())
+}
+
+fn has_token(req: &Request, header: &str, token: &str) -> bool {
+    req.headers
+        .get_all(header)
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+impl Router {
+    /// Accepts WebSocket connections on `url`. Use `websocket::upgrade` from
+    /// a regular handler when the connection needs anything from the request.
+    pub fn websocket<H>(&mut self, url: &str, handler: H)
+    where
+        H: Fn(WebSocket) + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.get(url, move |req| {
+            let handler = Arc::clone(&handler);
+            upgrade(&req, move |ws| handler(ws))
+        });
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// The server side of a WebSocket connection (RFC 6455).
+///
+/// Pings are answered automatically and a close from the client is echoed
+/// back, after which the connection is done:
+///
+/// ```ignore
+/// loop {
+///     match ws.recv() {
+///         Ok(Message::Text(text)) => ws.send_text(&text)?,
+///         Ok(Message::Close(_)) | Err(_) => break,
+///         Ok(_) => {}
+///     }
+/// }
+/// ```
+pub struct WebSocket {
+    io: BufReader<Upgraded>,
+    // a message whose first frames have arrived
+    fragments: Option<(u8, Vec<u8>)>,
+    // a frame that has partly arrived, kept when a read times out
+    partial: Vec<u8>,
+    max_message_size: usize,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket {
+    fn new(upgraded: Upgraded) -> Self {
+        Self {
+            io: BufReader::new(upgraded),
+            fragments: None,
+            partial: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Messages larger than this close the connection with 1009.
+    pub fn set_max_message_size(&mut self, max: usize) {
+        self.max_message_size = max;
+    }
+
+    /// How long `recv` waits for a frame, it waits forever by default.
+    ///
+    /// A `recv` that times out fails with an I/O error of kind `WouldBlock`
+    /// or `TimedOut`. Whatever part of a frame had arrived is kept, so the
+    /// next `recv` carries on where it stopped, e.g. after sending a ping.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.io.get_ref().set_read_timeout(timeout)
+    }
+
+    /// Waits for the next message, putting fragmented ones back together.
+    pub fn recv(&mut self) -> Result<Message, WebSocketError> {
+        if self.close_received {
+            return Err(WebSocketError::Closed);
+        }
+        loop {
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(e) => return Err(self.fail(e)),
+            };
+            match frame.opcode {
+                OP_PING => {
+                    if !self.close_sent {
+                        self.write_frame(OP_PONG, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OP_PONG => return Ok(Message::Pong(frame.payload)),
+                OP_CLOSE => {
+                    let close = match parse_close(&frame.payload) {
+                        Ok(close) => close,
+                        Err(e) => return Err(self.fail(e)),
+                    };
+                    self.close_received = true;
+                    if !self.close_sent {
+                        self.close_sent = true;
+                        // the echo carries the same status code
+                        self.write_frame(OP_CLOSE, frame.payload.get(..2).unwrap_or_default())?;
+                    }
+                    return Ok(Message::Close(close));
+                }
+                OP_TEXT | OP_BINARY if self.fragments.is_none() => {
+                    if frame.fin {
+                        return self.finish(frame.opcode, frame.payload);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                OP_CONTINUATION if self.fragments.is_some() => {
+                    let (opcode, mut data) = self.fragments.take().expect("checked above");
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.finish(opcode, data);
+                    }
+                    self.fragments = Some((opcode, data));
+                }
+                // a new message before the last one ended, or a stray continuation
+                _ => return Err(self.fail(WebSocketError::Protocol)),
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Text(text) => self.send_text(&text),
+            Message::Binary(data) => self.send_binary(&data),
+            Message::Ping(data) => self.ping(&data),
+            Message::Pong(data) => self.send_frame(OP_PONG, &data),
+            Message::Close(None) => self.close(None),
+            Message::Close(Some(frame)) => self.close(Some((frame.code, &frame.reason))),
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<(), WebSocketError> {
+        self.send_frame(OP_TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError> {
+        self.send_frame(OP_BINARY, data)
+    }
+
+    /// The payload can be at most 125 bytes.
+    pub fn ping(&mut self, data: &[u8]) -> Result<(), WebSocketError> {
+        if data.len() as u64 > MAX_CONTROL_PAYLOAD {
+            return Err(WebSocketError::Protocol);
+        }
+        self.send_frame(OP_PING, data)
+    }
+
+    /// Starts the close handshake. Keep calling `recv` until it returns the
+    /// client's `Message::Close` before dropping the connection.
+    pub fn close(&mut self, frame: Option<(u16, &str)>) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        let mut payload = Vec::new();
+        if let Some((code, reason)) = frame {
+            payload.extend_from_slice(&code.to_be_bytes());
+            payload.extend_from_slice(reason.as_bytes());
+            payload.truncate(MAX_CONTROL_PAYLOAD as usize);
+        }
+        self.close_sent = true;
+        self.write_frame(OP_CLOSE, &payload)
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        self.write_frame(opcode, payload)
+    }
+
+    fn finish(&mut self, opcode: u8, data: Vec<u8>) -> Result<Message, WebSocketError> {
+        if opcode == OP_BINARY {
+            return Ok(Message::Binary(data));
+        }
+        match String::from_utf8(data) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => Err(self.fail(WebSocketError::InvalidUtf8)),
+        }
+    }
+
+    // closes the connection with the status code for `e`, if it still can
+    fn fail(&mut self, e: WebSocketError) -> WebSocketError {
+        if let Some(code) = e.close_code() {
+            if !self.close_sent {
+                self.close_sent = true;
+                let _ = self.write_frame(OP_CLOSE, &code.to_be_bytes());
+            }
+            self.close_received = true;
+        }
+        e
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        self.fill_partial(2)?;
+        let head = [self.partial[0], self.partial[1]];
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        // no extensions are negotiated, so the reserved bits stay clear
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol);
+        }
+        // every frame from a client must be masked (RFC 6455 5.1)
+        if head[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol);
+        }
+        let (len, head_len) = match head[1] & 0x7f {
+            126 => {
+                self.fill_partial(4)?;
+                (u16::from_be_bytes([self.partial[2], self.partial[3]]) as u64, 4)
+            }
+            127 => {
+                self.fill_partial(10)?;
+                let len = u64::from_be_bytes(self.partial[2..10].try_into().expect("8 bytes"));
+                if len >> 63 != 0 {
+                    return Err(WebSocketError::Protocol);
+                }
+                (len, 10)
+            }
+            len => (len as u64, 2),
+        };
+        if opcode >= OP_CLOSE && (!fin || len > MAX_CONTROL_PAYLOAD) {
+            return Err(WebSocketError::Protocol);
+        }
+        let buffered = self.fragments.as_ref().map_or(0, |(_, data)| data.len() as u64);
+        if buffered + len > self.max_message_size as u64 {
+            return Err(WebSocketError::MessageTooLarge);
+        }
+
+        // the mask, then the payload
+        self.fill_partial(head_len + 4 + len as usize)?;
+        let mut payload = std::mem::take(&mut self.partial);
+        let mask: [u8; 4] = payload[head_len..head_len + 4].try_into().expect("4 bytes");
+        payload.drain(..head_len + 4);
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    // reads until the current frame has `len` bytes, keeping what arrived
+    // if a read fails
+    fn fill_partial(&mut self, len: usize) -> io::Result<()> {
+        while self.partial.len() < len {
+            let start = self.partial.len();
+            self.partial.resize(len, 0);
+            match self.io.read(&mut self.partial[start..]) {
+                Ok(0) => {
+                    self.partial.truncate(start);
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                Ok(n) => self.partial.truncate(start + n),
+                Err(e) => {
+                    self.partial.truncate(start);
+                    if e.kind() != ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // server frames go out whole and unmasked
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xffff => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        let io = self.io.get_mut();
+        io.write_all(&frame)?;
+        io.flush()?;
+        Ok(())
+    }
+}
+
+impl Debug for WebSocket {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("WebSocket")
+            .field("close_sent", &self.close_sent)
+            .field("close_received", &self.close_received)
+            .finish()
+    }
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<CloseFrame>, WebSocketError> {
+    let (code, reason) = match payload {
+        [] => return Ok(None),
+        [_] => return Err(WebSocketError::Protocol),
+        [a, b, reason @ ..] => (u16::from_be_bytes([*a, *b]), reason),
+    };
+    // codes that may be sent on the wire (RFC 6455 7.4)
+    let valid = matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999);
+    if !valid {
+        return Err(WebSocketError::Protocol);
+    }
+    let reason = String::from_utf8(reason.to_vec()).map_err(|_| WebSocketError::InvalidUtf8)?;
+    Ok(Some(CloseFrame { code, reason }))
+}
+
+pub enum WebSocketError {
+    Protocol,
+    InvalidUtf8,
+    MessageTooLarge,
+    /// The close handshake has started, nothing more can be sent or received.
+    Closed,
+    Io(io::Error),
+}
+
+impl WebSocketError {
+    fn error(&self) -> &str {
+        match self {
+            Self::Protocol => "WebSocket Protocol Error",
+            Self::InvalidUtf8 => "Invalid UTF-8 In Text Message",
+            Self::MessageTooLarge => "WebSocket Message Too Large",
+            Self::Closed => "WebSocket Closed",
+            Self::Io(_) => "WebSocket I/O Failed",
+        }
+    }
+
+    // the status code to close the connection with (RFC 6455 7.4.1)
+    fn close_code(&self) -> Option<u16> {
+        match self {
+            Self::Protocol => Some(1002),
+            Self::InvalidUtf8 => Some(1007),
+            Self::MessageTooLarge => Some(1009),
+            Self::Closed | Self::Io(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for WebSocketError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Error for WebSocketError {}
+
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Io(e) => write!(f, "{}: {}", self.error(), e),
+            _ => write!(f, "{}", self.error()),
+        }
+    }
+}
+
+impl Debug for WebSocketError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::Mutex};
+
+    use super::*;
+
+    // an ended connection that keeps whatever the server writes to it
+    struct Recorder(Arc<Mutex<Vec<u8>>>);
+
+    impl Read for Recorder {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // a socket that has received `input`, and what the server sends back
+    fn socket(input: Vec<u8>) -> (WebSocket, Arc<Mutex<Vec<u8>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let upgraded = Upgraded::new(Box::new(Recorder(Arc::clone(&written))), input, None);
+        (WebSocket::new(upgraded), written)
+    }
+
+    // a frame as a client sends it, masked
+    fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len @ 126..=0xffff => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[test]
+    fn unmasks_text_and_binary_messages() {
+        let big = vec![7; 70_000];
+        let input = [frame(true, OP_TEXT, b"hello"), frame(true, OP_BINARY, &[1; 300]), frame(true, OP_BINARY, &big)];
+        let (mut ws, _) = socket(input.concat());
+        assert_eq!(ws.recv().unwrap(), Message::Text("hello".to_string()));
+        assert_eq!(ws.recv().unwrap(), Message::Binary(vec![1; 300]));
+        assert_eq!(ws.recv().unwrap(), Message::Binary(big));
+    }
+
+    #[test]
+    fn joins_fragments_around_control_frames() {
+        let input = [
+            frame(false, OP_TEXT, b"hel"),
+            frame(true, OP_PING, b"are you there"),
+            frame(true, OP_CONTINUATION, b"lo"),
+        ];
+        let (mut ws, written) = socket(input.concat());
+        assert_eq!(ws.recv().unwrap(), Message::Ping(b"are you there".to_vec()));
+        assert_eq!(ws.recv().unwrap(), Message::Text("hello".to_string()));
+        // the pong goes out unmasked
+        assert_eq!(*written.lock().unwrap(), [&[0x80 | OP_PONG, 13][..], b"are you there"].concat());
+    }
+
+    #[test]
+    fn echoes_the_close_code() {
+        let (mut ws, written) = socket(frame(true, OP_CLOSE, b"\x03\xe8bye"));
+        let close = CloseFrame { code: 1000, reason: "bye".to_string() };
+        assert_eq!(ws.recv().unwrap(), Message::Close(Some(close)));
+        assert_eq!(*written.lock().unwrap(), [0x80 | OP_CLOSE, 2, 0x03, 0xe8]);
+        assert!(matches!(ws.recv(), Err(WebSocketError::Closed)));
+    }
+
+    // the error, and the close code the server answered with
+    fn failure(input: Vec<u8>, max_message_size: usize) -> (WebSocketError, Vec<u8>) {
+        let (mut ws, written) = socket(input);
+        ws.set_max_message_size(max_message_size);
+        let e = ws.recv().unwrap_err();
+        let written = written.lock().unwrap().clone();
+        (e, written)
+    }
+
+    #[test]
+    fn fails_protocol_violations_with_1002() {
+        let mut unmasked = frame(true, OP_TEXT, b"");
+        unmasked[1] &= 0x7f;
+        let mut reserved = frame(true, OP_TEXT, b"");
+        reserved[0] |= 0x40;
+        for input in [
+            unmasked,
+            reserved,
+            frame(false, OP_PING, b""),
+            frame(true, OP_PING, &[0; 126]),
+            frame(true, OP_CONTINUATION, b"stray"),
+            [frame(false, OP_TEXT, b"a"), frame(true, OP_TEXT, b"b")].concat(),
+            frame(true, OP_CLOSE, b"\x03\xed"),
+        ] {
+            let (e, written) = failure(input, DEFAULT_MAX_MESSAGE_SIZE);
+            assert!(matches!(e, WebSocketError::Protocol), "{:?}", e);
+            assert_eq!(written, [0x80 | OP_CLOSE, 2, 0x03, 0xea]);
+        }
+    }
+
+    #[test]
+    fn fails_invalid_utf8_with_1007() {
+        let (e, written) = failure(frame(true, OP_TEXT, b"\xff"), DEFAULT_MAX_MESSAGE_SIZE);
+        assert!(matches!(e, WebSocketError::InvalidUtf8));
+        assert_eq!(written, [0x80 | OP_CLOSE, 2, 0x03, 0xef]);
+    }
+
+    #[test]
+    fn fails_oversized_messages_with_1009() {
+        let input = [frame(false, OP_BINARY, &[0; 8]), frame(true, OP_CONTINUATION, &[0; 8])].concat();
+        let (e, written) = failure(input, 10);
+        assert!(matches!(e, WebSocketError::MessageTooLarge));
+        assert_eq!(written, [0x80 | OP_CLOSE, 2, 0x03, 0xf1]);
+    }
+
+    // a connection whose reads time out between the given pieces
+    struct Stalling(VecDeque<Vec<u8>>, bool);
+
+    impl Read for Stalling {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1 = !self.1;
+            if self.1 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let Some(mut piece) = self.0.pop_front() else {
+                return Ok(0);
+            };
+            let n = piece.len().min(buf.len());
+            buf[..n].copy_from_slice(&piece[..n]);
+            if n < piece.len() {
+                piece.drain(..n);
+                self.0.push_front(piece);
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for Stalling {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_timeout_keeps_the_part_of_a_frame_that_arrived() {
+        let input = [frame(true, OP_TEXT, b"hello"), frame(true, OP_BINARY, &[9; 200])].concat();
+        // cut at every stage of the frames: head, length, mask and payload
+        let pieces = [&input[..1], &input[1..3], &input[3..9], &input[9..13], &input[13..]];
+        let stalling = Stalling(pieces.iter().map(|p| p.to_vec()).collect(), false);
+        let mut ws = WebSocket::new(Upgraded::new(Box::new(stalling), Vec::new(), None));
+
+        let mut messages = Vec::new();
+        let mut timeouts = 0;
+        while messages.len() < 2 {
+            match ws.recv() {
+                Ok(message) => messages.push(message),
+                Err(WebSocketError::Io(e)) if e.kind() == ErrorKind::WouldBlock => timeouts += 1,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        assert!(timeouts >= pieces.len());
+        assert_eq!(messages, [Message::Text("hello".to_string()), Message::Binary(vec![9; 200])]);
+    }
+
+    #[test]
+    fn a_cut_off_frame_is_an_io_error() {
+        let mut input = frame(true, OP_TEXT, b"hello");
+        input.pop();
+        let (e, written) = failure(input, DEFAULT_MAX_MESSAGE_SIZE);
+        assert!(matches!(e, WebSocketError::Io(_)));
+        assert!(written.is_empty());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod server;
-pub mod http;
\ No newline at end of file
+pub mod http;
+pub mod pool;
+pub mod access_log;
+#[cfg(feature = "tls")]
+pub mod tls;
@@ -1,22 +1,28 @@
-use httpd::{server::Server, http::{Router, Response, StatusCode, Method}};
+use httpd::{server::Server, http::{Router, Response, StatusCode}};
+use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
 
 
 
 fn main() {
+    TermLogger::init(LevelFilter::Info, Config::default(), TerminalMode::Mixed, ColorChoice::Auto).unwrap();
+
     // server address
     let addr = String::from("127.0.0.1:8080");
 
 
     // create a new router
     let mut router = Router::new();
-    router.register("/", |req| {
-        match req.method {
-            Method::GET => Response::new(StatusCode::Ok, Some(String::from("<h1>Hello world!</h1>"))),
-            _ => Response::new(StatusCode::MethodNotAllowed, None)
-        }
+    router.get("/", |_| {
+        Response::builder(StatusCode::Ok)
+            .content_type("text/html; charset=utf-8")
+            .body("<h1>Hello world!</h1>")
+            .build()
+    });
+    router.post("/echo", |req| {
+        Response::new(StatusCode::Ok, Some(String::from_utf8_lossy(req.body).into_owned()))
     });
 
     // server gets the router
-    let server = Server::new(addr, router);
+    let server = Server::new(addr, router).handle_signals(true);
     server.run()
 }
\ No newline at end of file
@@ -0,0 +1,94 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// A fixed set of worker threads fed through a bounded queue.
+///
+/// Every queued item is passed to the same handler on whichever worker picks
+/// it up. When the queue is full the item is handed back to the caller instead
+/// of blocking, so it can be rejected gracefully.
+pub struct ThreadPool<T> {
+    workers: Vec<JoinHandle<()>>,
+    sender: Option<SyncSender<T>>,
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    pub fn new<F>(size: usize, queue_size: usize, handler: F) -> Self
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        assert!(size > 0, "thread pool needs at least one worker");
+
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                let handler = Arc::clone(&handler);
+                thread::Builder::new()
+                    .name(format!("httpd-worker-{}", id))
+                    .spawn(move || work(&receiver, &*handler))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+
+        Self { workers, sender: Some(sender) }
+    }
+
+    /// Queues `item` for a worker, or gives it back if the queue is full.
+    pub fn try_execute(&self, item: T) -> Result<(), T> {
+        let sender = self.sender.as_ref().expect("sender lives until drop");
+        sender.try_send(item).map_err(|e| match e {
+            TrySendError::Full(item) | TrySendError::Disconnected(item) => item,
+        })
+    }
+
+    /// Stops taking items and waits up to `timeout` for the workers to finish
+    /// what is already queued. Returns false if some were still busy, those
+    /// are left running in the background.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        drop(self.sender.take());
+        let deadline = Instant::now() + timeout;
+        while self.workers.iter().any(|w| !w.is_finished()) {
+            if Instant::now() >= deadline {
+                // dropping the handles detaches the threads
+                self.workers.clear();
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+}
+
+fn work<T>(receiver: &Mutex<Receiver<T>>, handler: &(dyn Fn(T) + Sync)) {
+    loop {
+        // the lock is released before the item is handled
+        let item = match receiver.lock().unwrap().recv() {
+            Ok(item) => item,
+            Err(_) => return,
+        };
+        // a panicking handler must not take the worker down with it
+        if panic::catch_unwind(AssertUnwindSafe(|| handler(item))).is_err() {
+            log::error!("worker {:?} recovered from a panic", thread::current().name());
+        }
+    }
+}
+
+impl<T> Drop for ThreadPool<T> {
+    // lets queued items drain, then waits for every worker to finish
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
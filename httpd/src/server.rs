@@ -1,56 +1,713 @@
 use std::{
-    io::Read,
-    net::{TcpListener, TcpStream},
+    collections::HashMap,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::http::{Response, Request, StatusCode, Router};
+use log::{debug, info, warn};
+
+use crate::access_log::{AccessLog, AccessRecord, LogFormat};
+use crate::http::{
+    upgrade::{Io, OnUpgrade},
+    BodyError, BodyReader, Response, Request, StatusCode, Router, Upgraded,
+};
+use crate::pool::ThreadPool;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+
+#[cfg(feature = "async")]
+mod event_loop;
+
+// request line plus headers
+const MAX_HEAD_LEN: u64 = 8 * 1024;
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+#[cfg(feature = "tls")]
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+// busy TLS connections waiting for their 503, any more are dropped
+#[cfg(feature = "tls")]
+const REJECT_QUEUE_SIZE: usize = 16;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 100;
+const DEFAULT_WORKERS: usize = 4;
+const DEFAULT_QUEUE_SIZE: usize = 64;
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct Server {
     addr: String,
     router: Router,
+    max_body_size: u64,
+    idle_timeout: Duration,
+    max_requests_per_connection: usize,
+    workers: usize,
+    queue_size: usize,
+    shutdown: ShutdownHandle,
+    shutdown_timeout: Duration,
+    handle_signals: bool,
+    access_log: AccessLog,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    #[cfg(feature = "tls")]
+    redirect_addr: Option<String>,
 }
 
 impl Server {
     pub fn new(addr: String, router: Router) -> Self {
-        Self { addr, router }
+        Self {
+            addr,
+            router,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_requests_per_connection: DEFAULT_MAX_REQUESTS_PER_CONNECTION,
+            workers: thread::available_parallelism().map_or(DEFAULT_WORKERS, |n| n.get()),
+            queue_size: DEFAULT_QUEUE_SIZE,
+            shutdown: ShutdownHandle::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            handle_signals: false,
+            access_log: AccessLog::new(LogFormat::Common),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "tls")]
+            redirect_addr: None,
+        }
+    }
+
+    /// Requests with a larger body are answered with 413 Payload Too Large.
+    /// Routes can set a limit of their own with `Router::max_body_size`.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// How long a kept-alive connection may sit without sending anything,
+    /// and how long a response may wait on a client that stopped reading.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The connection is closed after serving this many requests.
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        self.max_requests_per_connection = max.max(1);
+        self
+    }
+
+    /// Number of threads serving connections concurrently.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Connections accepted while every worker is busy wait in a queue of
+    /// this size; once it is full they are answered with 503.
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Where served requests are recorded, Common Log Format through the
+    /// `log` crate by default.
+    pub fn access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
+    /// Serve HTTPS instead of plain HTTP.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
     }
 
+    /// Also listen for plain HTTP on `addr`, answering every request there
+    /// with a redirect to the same URL over HTTPS.
+    #[cfg(feature = "tls")]
+    pub fn redirect_http(mut self, addr: String) -> Self {
+        self.redirect_addr = Some(addr);
+        self
+    }
+
+    /// How long `run` waits for in-flight requests once shut down.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Shut down gracefully on SIGINT or SIGTERM. A second signal exits
+    /// the process without waiting. Ignored on platforms without signals.
+    pub fn handle_signals(mut self, handle_signals: bool) -> Self {
+        self.handle_signals = handle_signals;
+        self
+    }
+
+    /// A handle that stops this server once it runs.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Serves connections until shut down through a `ShutdownHandle` or a
+    /// signal, then waits for in-flight requests before returning.
     pub fn run(self) {
         let listener = TcpListener::bind(&self.addr).unwrap();
-        println!("server started on {}", self.addr);
+        info!("server started on {} with {} workers", self.addr, self.workers);
+
+        let shutdown = self.shutdown.clone();
+        shutdown.state.listening_on(&listener);
+        #[cfg(unix)]
+        let signals = self.handle_signals.then(|| watch_signals(shutdown.clone()));
 
-        for stream in listener.incoming() {
-            match stream {
-                Ok(s) => self.handle_client(s),
-                Err(e) => println!("Failed to read from stream: {}", e),
+        #[cfg(feature = "tls")]
+        let redirect = self.redirect_addr.as_ref().map(|addr| {
+            assert!(self.tls.is_some(), "Server::redirect_http needs Server::tls");
+            let redirect = TcpListener::bind(addr).unwrap();
+            info!("redirecting http on {} to https", addr);
+            shutdown.state.listening_on(&redirect);
+            let https_port = listener.local_addr().map_or(443, |a| a.port());
+            (redirect, https_port)
+        });
+        // the handshake can stall, so TLS rejections get a thread of their own
+        #[cfg(feature = "tls")]
+        let rejecter = self.tls.clone().map(|tls| {
+            ThreadPool::new(1, REJECT_QUEUE_SIZE, move |stream| reject_busy_tls(&tls, stream))
+        });
+        #[cfg(feature = "tls")]
+        let reject = |stream| match &rejecter {
+            Some(rejecter) => {
+                if rejecter.try_execute(stream).is_err() {
+                    warn!("all workers busy, dropping connection");
+                }
             }
+            None => reject_busy(stream),
+        };
+        #[cfg(not(feature = "tls"))]
+        let reject = reject_busy;
+
+        let (workers, queue_size, timeout) = (self.workers, self.queue_size, self.shutdown_timeout);
+        let server = Arc::new(self);
+        let pool = Arc::new(ThreadPool::new(workers, queue_size, move |(s, redirect_to)| {
+            server.handle_client(s, redirect_to)
+        }));
+
+        #[cfg(feature = "tls")]
+        let redirect = redirect.map(|(listener, https_port)| {
+            let (pool, shutdown) = (Arc::clone(&pool), shutdown.clone());
+            thread::spawn(move || accept(&listener, &pool, &shutdown, Some(https_port), reject_busy))
+        });
+        accept(&listener, &pool, &shutdown, None, reject);
+        drop(listener);
+        #[cfg(feature = "tls")]
+        if let Some(rejecter) = rejecter {
+            rejecter.shutdown(REJECT_TIMEOUT);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(redirect) = redirect {
+            let _ = redirect.join();
+        }
+
+        info!("server shutting down");
+        let pool = Arc::into_inner(pool).expect("every acceptor has stopped");
+        if !pool.shutdown(timeout) {
+            warn!("shutdown timed out with requests still in flight");
+        }
+        #[cfg(unix)]
+        if let Some(signals) = signals {
+            signals.close();
         }
     }
 
-    fn handle_client(&self, mut stream: TcpStream) {
-        let mut buf = [0; 1024];
-    
-        match stream.read(&mut buf) {
-            Ok(_) => {
-                println!("Received a request: {}", String::from_utf8_lossy(&buf));
-                let resp = match Request::try_from(&buf[..]) {
-                    Ok(req) => {
-                        dbg!(&req);
-                        self.router.handle_request(req)
+    /// Like `run`, but serves every connection from a single event loop
+    /// (epoll, or kqueue on BSDs) and only occupies a worker while a handler
+    /// runs, so thousands of idle keep-alive connections are cheap. Streamed
+    /// responses are written from a worker. TLS isn't supported yet.
+    #[cfg(feature = "async")]
+    pub fn run_event_loop(self) {
+        event_loop::run(self)
+    }
+
+    // `redirect_to` is the HTTPS port for connections to the redirect listener
+    fn handle_client(&self, stream: TcpStream, redirect_to: Option<u16>) {
+        // a client that stops reading would otherwise hold the worker forever
+        let timeouts = stream
+            .set_read_timeout(Some(self.idle_timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.idle_timeout)));
+        if let Err(e) = timeouts {
+            warn!("Failed to set timeouts: {}", e);
+            return;
+        }
+        #[cfg(feature = "tls")]
+        if let (Some(tls), None) = (&self.tls, redirect_to) {
+            match stream.try_clone().and_then(|s| tls.accept(s)) {
+                Ok(tls_stream) => {
+                    if let Some(mut tls_stream) = self.serve(tls_stream, &stream, None) {
+                        tls_stream.conn.send_close_notify();
+                        let _ = tls_stream.flush();
+                    }
+                }
+                Err(e) => warn!("Failed to set up TLS: {}", e),
+            }
+            return;
+        }
+        match stream.try_clone() {
+            Ok(io) => {
+                self.serve(io, &stream, redirect_to);
+            }
+            Err(e) => warn!("Failed to clone stream: {}", e),
+        }
+    }
+
+    // Serves requests from `io` until the connection should close, then
+    // hands it back, unless it was upgraded to another protocol. `socket` is
+    // the connection underneath, for timeouts.
+    fn serve<S: Io + 'static>(&self, io: S, socket: &TcpStream, redirect_to: Option<u16>) -> Option<S> {
+        // pipelined requests wait in the reader's buffer until their turn
+        let mut reader = BufReader::new(io);
+
+        for served in 1..=self.max_requests_per_connection {
+            let last = served == self.max_requests_per_connection;
+            if served > 1 && !self.wait_for_request(&mut reader, socket) {
+                break;
+            }
+            match self.handle_request(&mut reader, socket, last, redirect_to) {
+                Outcome::KeepAlive => {}
+                Outcome::Close => break,
+                Outcome::Upgrade(on_upgrade) => {
+                    let buffered = reader.buffer().to_vec();
+                    let upgraded = Upgraded::new(Box::new(reader.into_inner()), buffered, socket.try_clone().ok());
+                    hand_over(on_upgrade, upgraded);
+                    return None;
+                }
+            }
+        }
+        Some(reader.into_inner())
+    }
+
+    // Waits for the next request on a kept-alive connection. Meanwhile the
+    // connection counts as idle, so shutting down closes it instead of
+    // waiting for it to time out.
+    fn wait_for_request<S: Read>(&self, reader: &mut BufReader<S>, socket: &TcpStream) -> bool {
+        // a pipelined request is already waiting
+        if !reader.buffer().is_empty() {
+            return true;
+        }
+        let Some(_idle) = self.shutdown.state.register_idle(socket) else {
+            return false;
+        };
+        matches!(reader.fill_buf(), Ok(buf) if !buf.is_empty())
+    }
+
+    // returns whether the connection should be kept open for another request
+    fn handle_request<S: Read + Write>(
+        &self,
+        reader: &mut BufReader<S>,
+        socket: &TcpStream,
+        last: bool,
+        redirect_to: Option<u16>,
+    ) -> Outcome {
+        let mut record = AccessRecord {
+            remote_addr: socket.peer_addr().ok(),
+            time: SystemTime::now(),
+            request_line: "-".to_string(),
+            referer: None,
+            user_agent: None,
+            status: 0,
+            bytes: None,
+            duration: Duration::ZERO,
+        };
+        let head = match read_head(reader) {
+            Ok(Some(head)) => head,
+            // the client closed or went idle between requests
+            Ok(None) => return Outcome::Close,
+            Err(e) if is_timeout(&e) => return Outcome::Close,
+            // e.g. plain HTTP sent to the HTTPS port, there is no one to answer
+            #[cfg(feature = "tls")]
+            Err(e) if e.get_ref().is_some_and(|e| e.is::<rustls::Error>()) => {
+                debug!("TLS error: {}", e);
+                return Outcome::Close;
+            }
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                debug!("Failed to parse request: {}", e);
+                let mut resp = Response::new(StatusCode::BadRequest, None);
+                resp.close_connection();
+                self.send(resp, reader.get_mut(), record);
+                return Outcome::Close;
+            }
+            Err(e) => {
+                debug!("Failed to read from stream: {}", e);
+                return Outcome::Close;
+            }
+        };
+        // timing starts once the whole head is in
+        let started = Instant::now();
+        record.time = SystemTime::now();
+        record.request_line = request_line(&head);
+
+        let mut unread_body = false;
+        let mut resp = match Request::try_from(&head[..]) {
+            Ok(mut req) => {
+                record.referer = req.headers.get("Referer").map(str::to_string);
+                record.user_agent = req.headers.get("User-Agent").map(str::to_string);
+                match self.read_body(&mut *reader, &req) {
+                    Ok(body) => {
+                        req.body = &body;
+                        let close = wants_close(&req);
+                        let mut resp = match redirect_to {
+                            Some(port) => https_redirect(&req, &record.request_line, port),
+                            None => self.router.handle_request(req),
+                        };
+                        if close {
+                            resp.close_connection();
+                        }
+                        resp
+                    }
+                    Err(BodyError::Io(e)) => {
+                        debug!("Failed to read from stream: {}", e);
+                        return Outcome::Close;
                     }
                     Err(e) => {
-                        println!("Failed to parse request: {}", e);
-                        Response::new(StatusCode::BadRequest, None)
+                        debug!("Failed to read request body: {}", e);
+                        // the rest of the body is still in flight, so the
+                        // connection can't be reused
+                        unread_body = true;
+                        Response::new(e.status_code(), None)
                     }
-                };
-                dbg!(&resp);
-                if let Err(e) = resp.send(&mut stream) {
-                    println!("failed to send resp: {}", e);
                 }
             }
-            Err(e) => println!("Failed to read from stream: {}", e),
+            Err(e) => {
+                debug!("Failed to parse request: {}", e);
+                // without a head there is no telling where a body would end
+                unread_body = true;
+                Response::new(StatusCode::BadRequest, None)
+            }
+        };
+        let upgrade = resp.take_upgrade();
+        // an upgraded connection outlives the limits on requests
+        if upgrade.is_none() && (last || unread_body || self.shutdown.is_shutting_down()) {
+            resp.close_connection();
+        }
+        let close = resp.closes_connection();
+        record.duration = started.elapsed();
+        if !self.send(resp, reader.get_mut(), record) {
+            return Outcome::Close;
+        }
+        if unread_body {
+            linger_close(socket);
+        }
+        match upgrade {
+            Some(on_upgrade) => Outcome::Upgrade(on_upgrade),
+            None if close => Outcome::Close,
+            None => Outcome::KeepAlive,
+        }
+    }
+
+    fn body_limit(&self, req: &Request) -> u64 {
+        self.router.body_limit(req).unwrap_or(self.max_body_size)
+    }
+
+    // sends the response and records it in the access log, returns false if
+    // it couldn't be sent
+    fn send(&self, mut resp: Response, stream: &mut impl Write, mut record: AccessRecord) -> bool {
+        let started = Instant::now();
+        let sent = resp.send(stream);
+        if let Err(e) = &sent {
+            debug!("Failed to send response: {}", e);
+        }
+        record.status = resp.status_code().as_u16();
+        record.bytes = sent.as_ref().ok().copied();
+        // a streamed body is only produced while it is sent
+        record.duration += started.elapsed();
+        self.access_log.record(&record);
+        sent.is_ok()
+    }
+
+    fn read_body<S: Read + Write>(&self, reader: &mut BufReader<S>, req: &Request) -> Result<Vec<u8>, BodyError> {
+        let mut body_reader = BodyReader::new(reader, &req.headers, self.body_limit(req))?;
+        if !body_reader.is_empty() && expects_continue(req) {
+            let stream = body_reader.get_mut().get_mut();
+            write!(stream, "HTTP/1.1 {}\r\n\r\n", StatusCode::Continue)?;
+            stream.flush()?;
         }
+        body_reader.read_all()
     }
 }
 
+// what happens to a connection after a response
+enum Outcome {
+    KeepAlive,
+    Close,
+    Upgrade(OnUpgrade),
+}
+
+// Gives an upgraded connection a thread of its own, as it may stay open for
+// as long as the client likes.
+fn hand_over(on_upgrade: OnUpgrade, upgraded: Upgraded) {
+    let _ = upgraded.set_read_timeout(None);
+    let _ = upgraded.set_write_timeout(None);
+    let spawned = thread::Builder::new()
+        .name("httpd-upgraded".to_string())
+        .spawn(move || on_upgrade.call(upgraded));
+    if let Err(e) = spawned {
+        warn!("Failed to spawn thread for upgraded connection: {}", e);
+    }
+}
+
+/// Stops a running `Server` from another thread. Cloning gives another
+/// handle to the same server.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+#[derive(Debug, Default)]
+struct ShutdownState {
+    stopping: AtomicBool,
+    // where the acceptors listen, to wake them up
+    addrs: Mutex<Vec<SocketAddr>>,
+    idle: Mutex<HashMap<u64, TcpStream>>,
+    next_id: AtomicU64,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting connections and closes idle ones. `Server::run`
+    /// returns once in-flight requests are done or its timeout passes.
+    pub fn shutdown(&self) {
+        let state = &self.state;
+        if state.stopping.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // the acceptors are blocked in accept until a connection comes in
+        for addr in state.addrs.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+            let _ = TcpStream::connect(wake_addr(*addr));
+        }
+        for (_, stream) in state.idle().drain() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.stopping.load(Ordering::SeqCst)
+    }
+}
+
+impl ShutdownState {
+    fn listening_on(&self, listener: &TcpListener) {
+        if let Ok(addr) = listener.local_addr() {
+            self.addrs.lock().unwrap_or_else(PoisonError::into_inner).push(addr);
+        }
+    }
 
+    fn idle(&self) -> MutexGuard<'_, HashMap<u64, TcpStream>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // None if the server is already shutting down
+    fn register_idle(&self, stream: &TcpStream) -> Option<IdleGuard<'_>> {
+        let stream = stream.try_clone().ok()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.idle().insert(id, stream);
+        let guard = IdleGuard { state: self, id };
+        // checked after registering, so shutdown either sees this connection
+        // or this connection sees the shutdown
+        if self.stopping.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(guard)
+    }
+}
+
+struct IdleGuard<'a> {
+    state: &'a ShutdownState,
+    id: u64,
+}
+
+impl Drop for IdleGuard<'_> {
+    fn drop(&mut self) {
+        self.state.idle().remove(&self.id);
+    }
+}
+
+// a listener on the unspecified address is reachable through loopback
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), addr.port()),
+        _ => addr,
+    }
+}
+
+#[cfg(unix)]
+fn watch_signals(shutdown: ShutdownHandle) -> signal_hook::iterator::Handle {
+    use signal_hook::{consts::{SIGINT, SIGTERM}, iterator::Signals};
+
+    let mut signals = Signals::new([SIGINT, SIGTERM]).expect("failed to register signal handlers");
+    let handle = signals.handle();
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if shutdown.is_shutting_down() {
+                warn!("received signal {} again, exiting", signal);
+                std::process::exit(1);
+            }
+            info!("received signal {}, shutting down", signal);
+            shutdown.shutdown();
+        }
+    });
+    handle
+}
+
+// Hands accepted connections to the pool until shut down. Busy connections
+// are handed to `reject`, which answers them with 503.
+fn accept(
+    listener: &TcpListener,
+    pool: &ThreadPool<(TcpStream, Option<u16>)>,
+    shutdown: &ShutdownHandle,
+    redirect_to: Option<u16>,
+    reject: impl Fn(TcpStream),
+) {
+    for stream in listener.incoming() {
+        if shutdown.is_shutting_down() {
+            break;
+        }
+        match stream {
+            Ok(s) => {
+                if let Err((s, _)) = pool.try_execute((s, redirect_to)) {
+                    reject(s);
+                }
+            }
+            Err(e) => warn!("Failed to accept connection: {}", e),
+        }
+    }
+}
+
+// Sends requests to the plain HTTP listener to the same URL over HTTPS.
+fn https_redirect(req: &Request, request_line: &str, https_port: u16) -> Response {
+    let Some(host) = req.headers.get("Host") else {
+        return Response::new(StatusCode::BadRequest, None);
+    };
+    // drop the port, leaving IPv6 literals like [::1] intact
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let target = request_line.split(' ').nth(1).filter(|t| t.starts_with('/')).unwrap_or("/");
+    let location = match https_port {
+        443 => format!("https://{}{}", host, target),
+        port => format!("https://{}:{}{}", host, port, target),
+    };
+    Response::builder(StatusCode::MovedPermanently)
+        .header("Location", &location)
+        .build()
+}
+
+// the first line of the head, for the access log
+fn request_line(head: &[u8]) -> String {
+    let line = head.split(|b| *b == b'\n').next().unwrap_or_default();
+    String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned()
+}
+
+// Reads up to and including the empty line that ends the headers, or returns
+// None if the connection is closed before a new request starts.
+fn read_head<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut head = Vec::new();
+    loop {
+        let remaining = MAX_HEAD_LEN - head.len() as u64;
+        let n = reader.take(remaining).read_until(b'\n', &mut head)?;
+        if n == 0 && head.is_empty() {
+            return Ok(None);
+        }
+        if n == 0 || !head.ends_with(b"\n") {
+            return Err(io::Error::new(ErrorKind::InvalidData, "request head too large or truncated"));
+        }
+        // stray empty lines before the request line are ignored (RFC 7230 3.5)
+        if head == b"\r\n" || head == b"\n" {
+            head.clear();
+        } else if head.ends_with(b"\n\r\n") || head.ends_with(b"\n\n") {
+            return Ok(Some(head));
+        }
+    }
+}
+
+// Runs on the accepting thread, so it must never block: whatever the client
+// already sent is discarded without waiting for more.
+fn reject_busy(mut stream: TcpStream) {
+    let mut resp = Response::new(StatusCode::ServiceUnavailable, None);
+    resp.close_connection();
+    if stream.set_nonblocking(true).is_ok() {
+        let mut buf = [0; 4096];
+        while matches!(stream.read(&mut buf), Ok(n) if n > 0) {}
+    }
+    warn!("all workers busy, rejecting connection");
+    if let Err(e) = resp.send(&mut stream) {
+        debug!("Failed to send response: {}", e);
+    }
+    let _ = stream.shutdown(Shutdown::Write);
+}
+
+// A plain text 503 means nothing to a TLS client, so this one goes through
+// the handshake first. That takes round trips, so it runs on the rejecting
+// thread rather than the acceptor, and a client that stalls holds it for
+// REJECT_TIMEOUT per read or write.
+#[cfg(feature = "tls")]
+fn reject_busy_tls(tls: &TlsConfig, stream: TcpStream) {
+    let mut resp = Response::new(StatusCode::ServiceUnavailable, None);
+    resp.close_connection();
+    warn!("all workers busy, rejecting connection");
+    let sent = stream
+        .set_read_timeout(Some(REJECT_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(REJECT_TIMEOUT)))
+        .and_then(|_| stream.try_clone())
+        .and_then(|s| tls.accept(s))
+        .and_then(|mut tls_stream| {
+            resp.send(&mut tls_stream)?;
+            tls_stream.conn.send_close_notify();
+            tls_stream.flush()
+        });
+    if let Err(e) = sent {
+        debug!("Failed to send response: {}", e);
+    }
+    let _ = stream.shutdown(Shutdown::Write);
+    // the request is left unread, see `linger_close`
+    if stream.set_nonblocking(true).is_ok() {
+        let mut buf = [0; 4096];
+        while matches!((&stream).read(&mut buf), Ok(n) if n > 0) {}
+    }
+}
+
+// Closing with unread input makes the kernel send a RST, which can discard the
+// response before the client reads it, so drain the input for a little while.
+fn linger_close(mut stream: &TcpStream) {
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(LINGER_TIMEOUT));
+    let deadline = Instant::now() + LINGER_TIMEOUT;
+    let mut buf = [0; 4096];
+    while Instant::now() < deadline {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+// HTTP/1.1 connections are persistent unless either side sends `close`
+fn wants_close(req: &Request) -> bool {
+    req.headers
+        .get_all("Connection")
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("close"))
+}
+
+fn expects_continue(req: &Request) -> bool {
+    req.headers
+        .get("Expect")
+        .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+}
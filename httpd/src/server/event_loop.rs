@@ -0,0 +1,697 @@
+// An epoll (kqueue on BSDs) backed alternative to the thread per connection
+// model in `Server::run`. One thread owns every socket and only hands complete
+// requests to the worker pool, so idle keep-alive connections cost a buffer
+// rather than a thread.
+
+use std::{
+    collections::HashMap,
+    io::{self, Empty, ErrorKind, Read, Write},
+    net::{self, Shutdown, SocketAddr},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{debug, error, info, warn};
+use mio::{
+    net::{TcpListener, TcpStream},
+    Events, Interest, Poll, Registry, Token, Waker,
+};
+
+use super::{expects_continue, hand_over, request_line, wants_close, Server, LINGER_TIMEOUT, MAX_HEAD_LEN};
+use crate::access_log::AccessRecord;
+use crate::http::{
+    upgrade::OnUpgrade, BodyError, BodyReader, Request, Response, ResponseBody, StatusCode, Upgraded,
+};
+use crate::pool::ThreadPool;
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+// how often timeouts and shutdown are checked
+const TICK: Duration = Duration::from_millis(250);
+const READ_CHUNK: usize = 16 * 1024;
+// bodies are decoded as they arrive, so only a head or a chunk line waits here
+const READ_LIMIT: usize = MAX_HEAD_LEN as usize + READ_CHUNK;
+
+enum Job {
+    // a complete request, to be answered by the router
+    Request {
+        token: Token,
+        head: Vec<u8>,
+        body: Vec<u8>,
+        record: AccessRecord,
+        last: bool,
+    },
+    // a streamed response, written on a worker since producing it may block
+    Stream {
+        token: Token,
+        stream: net::TcpStream,
+        resp: Response,
+        record: AccessRecord,
+    },
+}
+
+enum Reply {
+    Response {
+        token: Token,
+        bytes: Vec<u8>,
+        close: bool,
+        record: AccessRecord,
+        upgrade: Option<OnUpgrade>,
+    },
+    Stream {
+        token: Token,
+        resp: Response,
+        record: AccessRecord,
+    },
+    // a streamed response was sent and the connection is kept alive
+    Resume { token: Token, stream: net::TcpStream },
+    Closed { token: Token },
+    // the handler panicked before anything was sent
+    Failed { token: Token, request_line: String },
+}
+
+struct Connection {
+    // None while a worker streams a response on it
+    socket: Option<TcpStream>,
+    peer: Option<SocketAddr>,
+    read_buf: Vec<u8>,
+    // the request whose body is still coming in
+    pending: Option<Pending>,
+    write_buf: Vec<u8>,
+    written: usize,
+    // logged once the response is written
+    record: Option<AccessRecord>,
+    // the connection leaves the event loop once the response is written
+    upgrade: Option<OnUpgrade>,
+    in_flight: bool,
+    continue_sent: bool,
+    read_closed: bool,
+    close_after_write: bool,
+    // set once our side is shut down, the peer gets a moment to hang up
+    lingering: Option<Instant>,
+    served: usize,
+    last_active: Instant,
+}
+
+struct Pending {
+    head: Vec<u8>,
+    body: Vec<u8>,
+    decoder: BodyReader<Empty>,
+    expects_continue: bool,
+}
+
+enum Parsed {
+    Incomplete { expects_continue: bool },
+    Request { head: Vec<u8>, body: Vec<u8> },
+    Invalid(StatusCode),
+}
+
+pub(super) fn run(server: Server) {
+    #[cfg(feature = "tls")]
+    assert!(server.tls.is_none(), "Server::run_event_loop doesn't support TLS yet");
+
+    let listener = net::TcpListener::bind(&server.addr).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    info!("event loop server started on {} with {} workers", server.addr, server.workers);
+
+    let shutdown = server.shutdown.clone();
+    shutdown.state.listening_on(&listener);
+    #[cfg(unix)]
+    let signals = server.handle_signals.then(|| super::watch_signals(shutdown.clone()));
+
+    let poll = Poll::new().expect("failed to create poll");
+    let mut listener = Some(TcpListener::from_std(listener));
+    if let Some(listener) = &mut listener {
+        poll.registry()
+            .register(listener, LISTENER, Interest::READABLE)
+            .expect("failed to register listener");
+    }
+    let waker = Arc::new(Waker::new(poll.registry(), WAKER).expect("failed to create waker"));
+
+    let (replies, reply_rx) = mpsc::channel();
+    let (workers, queue_size, timeout) = (server.workers, server.queue_size, server.shutdown_timeout);
+    let server = Arc::new(server);
+    let pool = {
+        let (server, waker) = (Arc::clone(&server), Arc::clone(&waker));
+        ThreadPool::new(workers, queue_size, move |job: Job| {
+            let (token, request_line) = match &job {
+                Job::Request { token, record, .. } => (*token, Some(record.request_line.clone())),
+                Job::Stream { token, .. } => (*token, None),
+            };
+            // without a reply the connection would wait on the worker forever
+            let reply = panic::catch_unwind(AssertUnwindSafe(|| work(&server, job))).unwrap_or_else(|_| {
+                match request_line {
+                    Some(request_line) => {
+                        error!("handler panicked on \"{}\"", request_line);
+                        Reply::Failed { token, request_line }
+                    }
+                    // part of the response may be out already
+                    None => {
+                        error!("streamed response panicked, closing the connection");
+                        Reply::Closed { token }
+                    }
+                }
+            });
+            let _ = replies.send(reply);
+            let _ = waker.wake();
+        })
+    };
+
+    let mut event_loop = EventLoop {
+        server: &server,
+        registry: poll.registry().try_clone().expect("failed to clone registry"),
+        pool: &pool,
+        connections: HashMap::new(),
+        next_token: FIRST_CONNECTION,
+    };
+    let deadline = event_loop.serve(poll, &mut listener, &reply_rx, timeout);
+
+    info!("server shutting down");
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if !pool.shutdown(remaining) {
+        warn!("shutdown timed out with requests still in flight");
+    }
+    #[cfg(unix)]
+    if let Some(signals) = signals {
+        signals.close();
+    }
+}
+
+struct EventLoop<'a> {
+    server: &'a Server,
+    registry: Registry,
+    pool: &'a ThreadPool<Job>,
+    connections: HashMap<Token, Connection>,
+    next_token: usize,
+}
+
+impl EventLoop<'_> {
+    // returns the shutdown deadline once shut down
+    fn serve(
+        &mut self,
+        mut poll: Poll,
+        listener: &mut Option<TcpListener>,
+        replies: &Receiver<Reply>,
+        timeout: Duration,
+    ) -> Instant {
+        let mut events = Events::with_capacity(1024);
+        let mut deadline = None;
+        loop {
+            if let Err(e) = poll.poll(&mut events, Some(TICK)) {
+                if e.kind() != ErrorKind::Interrupted {
+                    panic!("poll failed: {}", e);
+                }
+            }
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => self.accept(listener.as_ref()),
+                    WAKER => {}
+                    token => self.advance(token),
+                }
+            }
+            for reply in replies.try_iter() {
+                self.handle_reply(reply);
+            }
+
+            if self.server.shutdown.is_shutting_down() {
+                let deadline = *deadline.get_or_insert_with(|| Instant::now() + timeout);
+                if let Some(mut listener) = listener.take() {
+                    let _ = self.registry.deregister(&mut listener);
+                }
+                // in-flight requests are finished, waiting ones dropped
+                self.connections.retain(|_, c| {
+                    c.in_flight || c.pending.is_some() || !c.write_buf.is_empty() || !c.read_buf.is_empty()
+                });
+                if self.connections.is_empty() || Instant::now() >= deadline {
+                    return deadline;
+                }
+            }
+            self.expire();
+        }
+    }
+
+    fn accept(&mut self, listener: Option<&TcpListener>) {
+        let Some(listener) = listener else {
+            return;
+        };
+        loop {
+            let (mut socket, peer) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    return;
+                }
+            };
+            if self.server.shutdown.is_shutting_down() {
+                continue;
+            }
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            if let Err(e) = self.registry.register(&mut socket, token, Interest::READABLE | Interest::WRITABLE) {
+                warn!("Failed to register connection: {}", e);
+                continue;
+            }
+            self.connections.insert(
+                token,
+                Connection {
+                    socket: Some(socket),
+                    peer: Some(peer),
+                    read_buf: Vec::new(),
+                    pending: None,
+                    write_buf: Vec::new(),
+                    written: 0,
+                    record: None,
+                    upgrade: None,
+                    in_flight: false,
+                    continue_sent: false,
+                    read_closed: false,
+                    close_after_write: false,
+                    lingering: None,
+                    served: 0,
+                    last_active: Instant::now(),
+                },
+            );
+        }
+    }
+
+    // Moves a connection along as far as it can go without blocking: writes
+    // what is pending, reads what has arrived and dispatches the next request.
+    fn advance(&mut self, token: Token) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        if conn.socket.is_none() {
+            return;
+        }
+        if conn.lingering.is_some() {
+            // whatever the peer still sends is thrown away
+            if conn.read(READ_LIMIT).is_err() || conn.read_closed {
+                self.close(token);
+            } else if let Some(conn) = self.connections.get_mut(&token) {
+                conn.read_buf.clear();
+            }
+            return;
+        }
+
+        match conn.flush() {
+            Ok(true) => {
+                if let Some(mut record) = conn.record.take() {
+                    record.duration += record_elapsed(&record);
+                    self.server.access_log.record(&record);
+                }
+                if let Some(on_upgrade) = conn.upgrade.take() {
+                    self.upgrade(token, on_upgrade);
+                    return;
+                }
+            }
+            Ok(false) => return,
+            Err(e) => {
+                debug!("Failed to send response: {}", e);
+                if let Some(mut record) = conn.record.take() {
+                    record.bytes = None;
+                    self.server.access_log.record(&record);
+                }
+                self.close(token);
+                return;
+            }
+        }
+        if conn.close_after_write {
+            self.linger(token);
+            return;
+        }
+        if conn.in_flight {
+            return;
+        }
+
+        loop {
+            let more = match conn.read(READ_LIMIT) {
+                Ok(more) => more,
+                Err(e) => {
+                    debug!("Failed to read from stream: {}", e);
+                    self.close(token);
+                    return;
+                }
+            };
+            match conn.parse(self.server) {
+                Parsed::Incomplete { .. } if conn.read_closed => self.close(token),
+                // the buffer was drained into the request, read on
+                Parsed::Incomplete { .. } if more => continue,
+                Parsed::Incomplete { expects_continue } => {
+                    if expects_continue && !conn.continue_sent {
+                        conn.continue_sent = true;
+                        conn.write_buf.extend_from_slice(format!("HTTP/1.1 {}\r\n\r\n", StatusCode::Continue).as_bytes());
+                        self.advance(token);
+                    }
+                }
+                Parsed::Invalid(status) => {
+                    let line = conn.request_line();
+                    conn.pending = None;
+                    self.respond_with(token, status, line)
+                }
+                Parsed::Request { head, body } => {
+                    conn.continue_sent = false;
+                    conn.in_flight = true;
+                    let record = new_record(conn.peer, request_line(&head));
+                    let last = conn.served + 1 >= self.server.max_requests_per_connection;
+                    let job = Job::Request { token, head, body, record, last };
+                    if let Err(Job::Request { record, .. }) = self.pool.try_execute(job) {
+                        warn!("all workers busy, rejecting request");
+                        self.respond_with(token, StatusCode::ServiceUnavailable, record.request_line);
+                    }
+                }
+            }
+            return;
+        }
+    }
+
+    // answers from the event loop itself and closes the connection, since
+    // the rest of the request can't be made sense of
+    fn respond_with(&mut self, token: Token, status: StatusCode, request_line: String) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        let mut resp = Response::new(status, None);
+        resp.close_connection();
+        let mut record = new_record(conn.peer, request_line);
+        record.status = status.as_u16();
+        record.bytes = Some(0);
+        conn.write_buf.clear();
+        conn.written = 0;
+        resp.send(&mut conn.write_buf).expect("writing to a Vec");
+        conn.record = Some(record);
+        conn.in_flight = false;
+        conn.close_after_write = true;
+        self.advance(token);
+    }
+
+    fn handle_reply(&mut self, reply: Reply) {
+        match reply {
+            Reply::Response { token, bytes, close, record, upgrade } => {
+                let Some(conn) = self.connections.get_mut(&token) else {
+                    return;
+                };
+                conn.write_buf = bytes;
+                conn.written = 0;
+                conn.record = Some(record);
+                conn.upgrade = upgrade;
+                conn.close_after_write = close;
+                conn.in_flight = false;
+                conn.served += 1;
+                self.advance(token);
+            }
+            Reply::Stream { token, resp, record } => {
+                let Some(conn) = self.connections.get_mut(&token) else {
+                    return;
+                };
+                let Some(mut socket) = conn.socket.take() else {
+                    return;
+                };
+                let _ = self.registry.deregister(&mut socket);
+                let stream = net::TcpStream::from(socket);
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_write_timeout(Some(self.server.idle_timeout)))
+                {
+                    warn!("Failed to hand connection to a worker: {}", e);
+                    self.connections.remove(&token);
+                    return;
+                }
+                // nothing has been sent yet, so the client can still be told
+                if let Err(Job::Stream { stream, record, .. }) =
+                    self.pool.try_execute(Job::Stream { token, stream, resp, record })
+                {
+                    warn!("all workers busy, rejecting streamed response");
+                    if self.reattach(token, stream) {
+                        self.respond_with(token, StatusCode::ServiceUnavailable, record.request_line);
+                    }
+                }
+            }
+            Reply::Resume { token, stream } => {
+                if !self.reattach(token, stream) {
+                    return;
+                }
+                if let Some(conn) = self.connections.get_mut(&token) {
+                    conn.in_flight = false;
+                    conn.served += 1;
+                    conn.last_active = Instant::now();
+                }
+                self.advance(token);
+            }
+            Reply::Closed { token } => {
+                self.connections.remove(&token);
+            }
+            Reply::Failed { token, request_line } => {
+                self.respond_with(token, StatusCode::InternalServerError, request_line);
+            }
+        }
+    }
+
+    // puts a connection back into the event loop after a worker had it,
+    // dropping it if that fails
+    fn reattach(&mut self, token: Token, stream: net::TcpStream) -> bool {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return false;
+        };
+        if stream.set_nonblocking(true).is_err() {
+            self.connections.remove(&token);
+            return false;
+        }
+        let mut socket = TcpStream::from_std(stream);
+        if self.registry.register(&mut socket, token, Interest::READABLE | Interest::WRITABLE).is_err() {
+            self.connections.remove(&token);
+            return false;
+        }
+        conn.socket = Some(socket);
+        true
+    }
+
+    // takes the connection out of the event loop and gives it to `on_upgrade`
+    fn upgrade(&mut self, token: Token, on_upgrade: OnUpgrade) {
+        let Some(mut conn) = self.connections.remove(&token) else {
+            return;
+        };
+        let Some(mut socket) = conn.socket.take() else {
+            return;
+        };
+        let _ = self.registry.deregister(&mut socket);
+        let stream = net::TcpStream::from(socket);
+        match stream.set_nonblocking(false).and_then(|_| stream.try_clone()) {
+            Ok(io) => hand_over(on_upgrade, Upgraded::new(Box::new(io), conn.read_buf, Some(stream))),
+            Err(e) => warn!("Failed to hand over upgraded connection: {}", e),
+        }
+    }
+
+    // Closing with unread input makes the kernel send a RST, which can
+    // discard the response, so the write side is shut first and the
+    // connection dropped once the peer hangs up or LINGER_TIMEOUT passes.
+    fn linger(&mut self, token: Token) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        if conn.read_closed {
+            self.close(token);
+            return;
+        }
+        if let Some(socket) = &conn.socket {
+            let _ = socket.shutdown(Shutdown::Write);
+        }
+        conn.lingering = Some(Instant::now() + LINGER_TIMEOUT);
+        self.advance(token);
+    }
+
+    fn close(&mut self, token: Token) {
+        if let Some(mut conn) = self.connections.remove(&token) {
+            if let Some(socket) = &mut conn.socket {
+                let _ = self.registry.deregister(socket);
+            }
+        }
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        let idle_timeout = self.server.idle_timeout;
+        self.connections.retain(|_, conn| {
+            if conn.in_flight || conn.socket.is_none() {
+                return true;
+            }
+            match conn.lingering {
+                Some(until) => until > now,
+                None => now.duration_since(conn.last_active) < idle_timeout,
+            }
+        });
+    }
+}
+
+impl Connection {
+    // Reads what has arrived, as long as the buffer stays under `limit`.
+    // Returns whether it stopped at the limit with more possibly waiting.
+    fn read(&mut self, limit: usize) -> io::Result<bool> {
+        let Some(socket) = &mut self.socket else {
+            return Ok(false);
+        };
+        let mut buf = [0; READ_CHUNK];
+        while !self.read_closed && self.read_buf.len() < limit {
+            match socket.read(&mut buf) {
+                Ok(0) => self.read_closed = true,
+                Ok(n) => {
+                    self.read_buf.extend_from_slice(&buf[..n]);
+                    self.last_active = Instant::now();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(!self.read_closed)
+    }
+
+    // returns whether everything pending was written
+    fn flush(&mut self) -> io::Result<bool> {
+        let Some(socket) = &mut self.socket else {
+            return Ok(false);
+        };
+        while self.written < self.write_buf.len() {
+            match socket.write(&self.write_buf[self.written..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.written += n;
+                    self.last_active = Instant::now();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        self.write_buf.clear();
+        self.written = 0;
+        Ok(true)
+    }
+
+    // Takes the request at the front of `read_buf` as far as it has arrived,
+    // the same way the blocking server reads one off a connection. Its head
+    // and the body decoded so far are kept in `pending` between calls.
+    fn parse(&mut self, server: &Server) -> Parsed {
+        if self.pending.is_none() {
+            // stray empty lines before the request line are ignored (RFC 7230 3.5)
+            let buf = &mut self.read_buf;
+            while let Some(len) = [&b"\r\n"[..], b"\n"].iter().find(|eol| buf.starts_with(eol)).map(|eol| eol.len()) {
+                buf.drain(..len);
+            }
+
+            let Some(head_len) = find_head_end(buf) else {
+                return if buf.len() as u64 >= MAX_HEAD_LEN {
+                    Parsed::Invalid(StatusCode::BadRequest)
+                } else {
+                    Parsed::Incomplete { expects_continue: false }
+                };
+            };
+            let Ok(req) = Request::try_from(&buf[..head_len]) else {
+                return Parsed::Invalid(StatusCode::BadRequest);
+            };
+            let decoder = match BodyReader::new(io::empty(), &req.headers, server.body_limit(&req)) {
+                Ok(decoder) => decoder,
+                Err(e) => return Parsed::Invalid(e.status_code()),
+            };
+            let expects_continue = expects_continue(&req);
+            let head = buf.drain(..head_len).collect();
+            self.pending = Some(Pending { head, body: Vec::new(), decoder, expects_continue });
+        }
+
+        let pending = self.pending.as_mut().expect("pending request");
+        match pending.decoder.feed(&self.read_buf, &mut pending.body) {
+            Ok((used, done)) => {
+                self.read_buf.drain(..used);
+                if !done {
+                    return Parsed::Incomplete { expects_continue: pending.expects_continue };
+                }
+                let Pending { head, body, .. } = self.pending.take().expect("pending request");
+                Parsed::Request { head, body }
+            }
+            Err(BodyError::Io(_)) => Parsed::Invalid(StatusCode::BadRequest),
+            Err(e) => Parsed::Invalid(e.status_code()),
+        }
+    }
+
+    fn request_line(&self) -> String {
+        match &self.pending {
+            Some(pending) => request_line(&pending.head),
+            None => request_line(&self.read_buf),
+        }
+    }
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    let window = &buf[..buf.len().min(MAX_HEAD_LEN as usize)];
+    window
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b'\n')
+        .find_map(|(i, _)| match &window[..i] {
+            [.., b'\n'] => Some(i + 1),
+            [.., b'\n', b'\r'] => Some(i + 1),
+            _ => None,
+        })
+}
+
+fn new_record(peer: Option<SocketAddr>, request_line: String) -> AccessRecord {
+    AccessRecord {
+        remote_addr: peer,
+        time: SystemTime::now(),
+        request_line,
+        referer: None,
+        user_agent: None,
+        status: 0,
+        bytes: None,
+        duration: Duration::ZERO,
+    }
+}
+
+// time since the request came in that isn't in `record.duration` yet
+fn record_elapsed(record: &AccessRecord) -> Duration {
+    SystemTime::now()
+        .duration_since(record.time)
+        .unwrap_or_default()
+        .saturating_sub(record.duration)
+}
+
+fn work(server: &Server, job: Job) -> Reply {
+    match job {
+        Job::Request { token, head, body, mut record, last } => {
+            let mut req = Request::try_from(&head[..]).expect("parsed by the event loop");
+            req.body = &body;
+            record.referer = req.headers.get("Referer").map(str::to_string);
+            record.user_agent = req.headers.get("User-Agent").map(str::to_string);
+            let close = last || wants_close(&req);
+            let mut resp = server.router.handle_request(req);
+            let upgrade = resp.take_upgrade();
+            if upgrade.is_none() && (close || server.shutdown.is_shutting_down()) {
+                resp.close_connection();
+            }
+            if upgrade.is_none() && matches!(resp.body(), ResponseBody::Stream { .. }) {
+                return Reply::Stream { token, resp, record };
+            }
+
+            let mut bytes = Vec::new();
+            let sent = resp.send(&mut bytes).expect("writing to a Vec");
+            record.status = resp.status_code().as_u16();
+            record.bytes = Some(sent);
+            Reply::Response { token, bytes, close: resp.closes_connection(), record, upgrade }
+        }
+        Job::Stream { token, mut stream, resp, mut record } => {
+            let close = resp.closes_connection();
+            record.duration += record_elapsed(&record);
+            if server.send(resp, &mut stream, record) && !close {
+                Reply::Resume { token, stream }
+            } else {
+                let _ = stream.shutdown(Shutdown::Both);
+                Reply::Closed { token }
+            }
+        }
+    }
+}
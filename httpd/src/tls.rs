@@ -0,0 +1,70 @@
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    fs,
+    io::{self, ErrorKind},
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+};
+
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+/// A TLS connection over a client socket.
+pub(crate) type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Certificates and settings for serving HTTPS, see `Server::tls`.
+///
+/// `examples/tls.rs` serves one bundled with the repository. Another
+/// self-signed certificate for local testing can be made with:
+///
+/// ```text
+/// openssl req -x509 -newkey rsa:2048 -nodes -days 30 -subj /CN=localhost \
+///     -keyout key.pem -out cert.pem
+/// ```
+#[derive(Clone)]
+pub struct TlsConfig {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsConfig {
+    /// Loads a PEM certificate chain, leaf first, and its PEM private key
+    /// (PKCS#1, PKCS#8 or SEC1).
+    pub fn from_pem_files(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_pem(&fs::read(cert_path)?, &fs::read(key_path)?)
+    }
+
+    pub fn from_pem(cert_pem: &[u8], key_pem: &[u8]) -> io::Result<Self> {
+        let certs = rustls_pemfile::certs(&mut &cert_pem[..]).collect::<Result<Vec<_>, _>>()?;
+        if certs.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "no certificate found in PEM"));
+        }
+        let key = rustls_pemfile::private_key(&mut &key_pem[..])?
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "no private key found in PEM"))?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Self::from_server_config(Arc::new(config)))
+    }
+
+    /// Uses a rustls configuration built elsewhere, e.g. to require client
+    /// certificates.
+    pub fn from_server_config(config: Arc<ServerConfig>) -> Self {
+        Self { config }
+    }
+
+    // the handshake happens on the first read or write
+    pub(crate) fn accept(&self, stream: TcpStream) -> io::Result<TlsStream> {
+        let conn = ServerConnection::new(Arc::clone(&self.config)).map_err(io::Error::other)?;
+        Ok(StreamOwned::new(conn, stream))
+    }
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("TlsConfig").field("alpn_protocols", &self.config.alpn_protocols).finish()
+    }
+}